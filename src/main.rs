@@ -9,13 +9,16 @@ use tokio::io::{AsyncBufReadExt,  BufReader};
 use crate::utils::wav::{write_audio_chunk, WavHeader};
 use clap::Parser;
 use std::net::SocketAddr;
-use tts::koko::TTSKoko;
+use tts::koko::{TTSKoko, TTSOpts};
 
 #[derive(Parser, Debug)]
 #[command(name = "kokoros")]
 #[command(version = "0.1")]
 #[command(author = "Lucas Jin")]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     #[arg(short = 't', long = "text", value_name = "TEXT")]
     text: Option<String>,
 
@@ -30,6 +33,20 @@ struct Cli {
     #[arg(short = 'm', long = "model", value_name = "MODEL")]
     model: Option<String>,
 
+    #[arg(
+        long = "quantized-model",
+        value_name = "MODEL",
+        help = "Optional quantized model, selected with `\"model\": \"quantized\"` (OpenAI server)"
+    )]
+    quantized_model: Option<String>,
+
+    #[arg(
+        long = "quantized-voice",
+        value_name = "VOICE",
+        help = "Voice served by the quantized model unless the request names a model (repeatable)"
+    )]
+    quantized_voices: Vec<String>,
+
     #[arg(short = 's', long = "style", value_name = "STYLE")]
     style: Option<String>,
 
@@ -38,11 +55,220 @@ struct Cli {
 
     #[arg(long = "stream", help = "Enable streaming mode")]
     stream: bool,
+
+    #[arg(
+        long = "first-chunk-words",
+        value_name = "WORDS",
+        help = "In streaming mode, synthesize this many leading words first to start playback sooner"
+    )]
+    first_chunk_words: Option<usize>,
+
+    #[arg(
+        long = "encoder-retries",
+        value_name = "COUNT",
+        default_value_t = 2,
+        help = "How often a transient audio encoder failure is retried (OpenAI server)"
+    )]
+    encoder_retries: u32,
+
+    #[arg(
+        long = "webhook-allow-host",
+        value_name = "HOST",
+        help = "Host that job webhooks may be sent to, including its subdomains (repeatable)"
+    )]
+    webhook_allow_hosts: Vec<String>,
+
+    #[arg(long = "webhook-timeout-ms", value_name = "MS", default_value_t = 5000)]
+    webhook_timeout_ms: u64,
+
+    #[arg(long = "webhook-retries", value_name = "COUNT", default_value_t = 2)]
+    webhook_retries: u32,
+
+    #[arg(
+        long = "max-connections",
+        value_name = "COUNT",
+        default_value_t = serve::server::DEFAULT_MAX_CONNECTIONS,
+        help = "Maximum simultaneously open client connections; more are rejected with 503"
+    )]
+    max_connections: usize,
+
+    #[cfg(feature = "tls")]
+    #[arg(
+        long = "tls-cert",
+        value_name = "FILE",
+        requires = "tls_key",
+        help = "PEM certificate chain; serve HTTPS instead of HTTP (OpenAI server)"
+    )]
+    tls_cert: Option<String>,
+
+    #[cfg(feature = "tls")]
+    #[arg(
+        long = "tls-key",
+        value_name = "FILE",
+        requires = "tls_cert",
+        help = "PEM private key for --tls-cert"
+    )]
+    tls_key: Option<String>,
+
+    #[arg(
+        long = "voice-pool",
+        value_name = "VOICE",
+        help = "Voice picked for requests that do not name one (repeatable, OpenAI server)"
+    )]
+    voice_pool: Vec<String>,
+
+    #[arg(
+        long = "voice-fallbacks",
+        value_name = "FILE",
+        help = "JSON object of ordered substitutes per voice, used while a voice is unavailable (OpenAI server)"
+    )]
+    voice_fallbacks: Option<String>,
+
+    #[arg(
+        long = "voice-pool-strategy",
+        value_enum,
+        default_value_t = serve::voices::PoolStrategy::RoundRobin,
+        help = "How a voice is picked from the pool: cycle through it, or hash the request's `user`"
+    )]
+    voice_pool_strategy: serve::voices::PoolStrategy,
+
+    #[arg(
+        long = "max-chunks",
+        value_name = "COUNT",
+        help = "Merge adjacent chunks so a request is synthesized in at most this many (OpenAI server)"
+    )]
+    max_chunks: Option<usize>,
+
+    #[arg(
+        long = "debug-endpoints",
+        help = "Serve debugging endpoints such as /debug/formats (OpenAI server)"
+    )]
+    debug_endpoints: bool,
+
+    #[arg(
+        long = "mp3-min-bitrate",
+        value_name = "KBPS",
+        help = "Encode MP3 as VBR that never drops below this bitrate, e.g. 32 (OpenAI server)"
+    )]
+    mp3_min_bitrate: Option<u32>,
+
+    #[arg(
+        long = "recent-requests",
+        value_name = "COUNT",
+        default_value_t = serve::recent::DEFAULT_CAPACITY,
+        help = "How many recent requests /admin/recent-requests lists; 0 disables it (OpenAI server)"
+    )]
+    recent_requests: usize,
+
+    #[arg(
+        long = "report-clipping",
+        help = "Report in response headers whether samples beyond full scale had to be limited (OpenAI server)"
+    )]
+    report_clipping: bool,
+
+    #[arg(
+        long = "default-speed",
+        value_name = "SPEED",
+        default_value_t = 1.0,
+        help = "Speed for requests that omit `speed`; can be changed at runtime via /admin/default-speed"
+    )]
+    default_speed: f32,
+
+    #[arg(
+        long = "admin-token",
+        value_name = "TOKEN",
+        help = "Bearer token for the /admin endpoints, which are disabled without one"
+    )]
+    admin_token: Option<String>,
+
+    #[arg(
+        long = "silence-retry-rms",
+        value_name = "RMS",
+        help = "Re-synthesize chunks whose RMS level is below this, e.g. 0.001 (OpenAI server)"
+    )]
+    silence_retry_rms: Option<f32>,
+
+    #[arg(
+        long = "silence-retries",
+        value_name = "COUNT",
+        default_value_t = 1,
+        help = "How often a silent chunk is re-synthesized before the request fails"
+    )]
+    silence_retries: u32,
+
+    #[arg(
+        long = "elongation",
+        value_enum,
+        default_value_t = tts::normalize::ElongationPolicy::Literal,
+        help = "How elongations like \"soooo!!!\" are read unless a request chooses (OpenAI server)"
+    )]
+    elongation: tts::normalize::ElongationPolicy,
+
+    #[arg(
+        long = "exceptions",
+        value_name = "FILE",
+        help = "JSON list of patterns that text normalization leaves verbatim or spells as given"
+    )]
+    exceptions: Option<String>,
+
+    #[arg(
+        long = "punctuation-pauses",
+        help = "Insert pauses after punctuation, with built-in durations per language"
+    )]
+    punctuation_pauses: bool,
+
+    #[arg(
+        long = "pause-map",
+        value_name = "FILE",
+        help = "JSON of pause durations in ms per language and punctuation mark; implies --punctuation-pauses"
+    )]
+    pause_map: Option<String>,
+
+    #[arg(
+        long = "lint",
+        help = "Check that the phonemes of the configuration (e.g. --exceptions) are known to the model, then exit; fails on problems"
+    )]
+    lint: bool,
+
+    #[arg(
+        long = "sentence-splitter",
+        value_enum,
+        default_value_t = tts::normalize::SentenceSplitter::Simple,
+        help = "Sentence splitter used for chunking; robust keeps abbreviations like \"Dr.\" together"
+    )]
+    sentence_splitter: tts::normalize::SentenceSplitter,
+
+    #[arg(
+        long = "clips",
+        value_name = "FILE",
+        help = "JSON object of intro/outro clip names and WAV paths that requests can select (OpenAI server)"
+    )]
+    clips: Option<String>,
+
+    #[arg(
+        long = "eq-presets",
+        value_name = "FILE",
+        help = "JSON object of per-voice EQ bands applied after synthesis (OpenAI server)"
+    )]
+    eq_presets: Option<String>,
+
+    #[arg(
+        long = "fingerprint",
+        help = "Report the model hash, voice, version and settings used in every response"
+    )]
+    fingerprint: bool,
+}
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Fire speech requests at a running server and report latency, throughput and errors
+    Bench(serve::bench::BenchArgs),
 }
+
 async fn handle_streaming_mode(
     tts: &TTSKoko,
     lan: &str,
     style: &str,
+    opts: &TTSOpts,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let stdin = tokio::io::stdin();
     let reader = BufReader::new(stdin);
@@ -55,7 +281,7 @@ async fn handle_streaming_mode(
     eprintln!("Entering streaming mode. Type text and press Enter. Use Ctrl+D to exit.");
 
     let header = WavHeader::new(1, 24000, 32);
-    header.write_header(&mut stdout)?;
+    header.write_header(&mut stdout, None)?;
     stdout.flush()?;
 
     while let Some(line) = lines.next_line().await? {
@@ -63,14 +289,14 @@ async fn handle_streaming_mode(
             continue;
         }
 
-        // Process the line and get audio data
-        match tts.tts_raw_audio(&line, lan, style) {
-            Ok(raw_audio) => {
-                // Write the raw audio samples directly
-                write_audio_chunk(&mut stdout, &raw_audio)?;
-                stdout.flush()?;
-            }
-            Err(e) => eprintln!("Error processing line: {}", e),
+        // Process the line, writing the raw audio samples of each chunk as soon as it is ready
+        let result = tts.tts_raw_audio_streaming(&line, lan, style, opts, |raw_audio| {
+            write_audio_chunk(&mut stdout, &raw_audio)?;
+            stdout.flush()?;
+            Ok(())
+        });
+        if let Err(e) = result {
+            eprintln!("Error processing line: {}", e);
         }
     }
 
@@ -80,8 +306,15 @@ async fn handle_streaming_mode(
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let rt = tokio::runtime::Runtime::new()?;
     rt.block_on(async {
+        let started_at = std::time::Instant::now();
         let args = Cli::parse();
 
+        if let Some(Command::Bench(bench)) = &args.command {
+            let report = serve::bench::run(bench).await?;
+            println!("{}", report);
+            return Ok(());
+        }
+
         // if users use `af_sky.4+af_nicho.3` as style name
         // then we blend it, with 0.4 af_sky + 0.3 af_nicho
 
@@ -89,20 +322,136 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         let style = args.style.unwrap_or_else(|| "af_sarah.4+af_nicole.6".to_string());
         let lan = args.lan.unwrap_or_else(|| { "en-us".to_string() });
 
+        let exceptions = match &args.exceptions {
+            Some(path) => tts::normalize::Exceptions::from_json(utils::fileio::load_json_file(path)?)?,
+            None => tts::normalize::Exceptions::default(),
+        };
+        let exceptions = std::sync::Arc::new(exceptions);
+
+        let pauses = match &args.pause_map {
+            Some(path) => Some(tts::normalize::PauseMap::from_json(utils::fileio::load_json_file(path)?)?),
+            None => args.punctuation_pauses.then(tts::normalize::PauseMap::default),
+        };
+        let pauses = pauses.map(std::sync::Arc::new);
+
+        let lint_issues = tts::lint::lint_exceptions(&exceptions, &lan);
+        for issue in &lint_issues {
+            eprintln!("Lint: {}", issue);
+        }
+        if args.lint {
+            if !lint_issues.is_empty() {
+                return Err(format!("{} lint problem(s) found", lint_issues.len()).into());
+            }
+            eprintln!("Lint: no problems found");
+            return Ok(());
+        }
+
         let tts = TTSKoko::new(&model_path).await;
 
         if args.stream {
-            handle_streaming_mode(&tts, &lan, &style).await?;
+            let opts = TTSOpts {
+                first_chunk_words: args.first_chunk_words,
+                exceptions,
+                sentence_splitter: args.sentence_splitter,
+                pauses,
+                ..TTSOpts::default()
+            };
+            handle_streaming_mode(&tts, &lan, &style, &opts).await?;
             Ok(())
         } else if args.oai {
-            let app = serve::openai::create_server(tts).await;
+            let voices = tts.voices();
+            if let Some(unknown) = args.voice_pool.iter().find(|v| !voices.contains(v)) {
+                return Err(format!("Unknown voice in voice pool: {}", unknown).into());
+            }
+            if let Some(kbps) = args.mp3_min_bitrate {
+                if !serve::openai::MP3_KILOBITRATES.contains(&kbps) {
+                    return Err(format!(
+                        "--mp3-min-bitrate must be one of {:?}",
+                        serve::openai::MP3_KILOBITRATES
+                    )
+                    .into());
+                }
+            }
+            if !serve::admin::SPEED_RANGE.contains(&args.default_speed) {
+                return Err("--default-speed must be between 0.25 and 4.0".into());
+            }
+            let voice_pool = serve::voices::VoicePool::new(args.voice_pool, args.voice_pool_strategy);
+            let voice_fallbacks = match &args.voice_fallbacks {
+                Some(path) => Some(serve::voices::VoiceFallbacks::from_json(utils::fileio::load_json_file(path)?)?),
+                None => None,
+            };
+            for voice in voice_fallbacks.iter().flat_map(|fallbacks| fallbacks.voices()) {
+                if !voices.iter().any(|v| v == voice) {
+                    eprintln!("Voice {} in the fallback chains is not loaded", voice);
+                }
+            }
+            let eq_presets: std::collections::HashMap<String, Vec<utils::dsp::EqBand>> = match &args.eq_presets {
+                Some(path) => serde_json::from_value(utils::fileio::load_json_file(path)?)
+                    .map_err(|e| format!("Invalid EQ presets: {}", e))?,
+                None => Default::default(),
+            };
+            for (voice, bands) in &eq_presets {
+                if !voices.contains(voice) {
+                    return Err(format!("Unknown voice in EQ presets: {}", voice).into());
+                }
+                utils::dsp::Equalizer::new(bands, TTSKoko::SAMPLE_RATE)
+                    .map_err(|e| format!("Invalid EQ preset for {}: {}", voice, e))?;
+            }
+            let clips = match &args.clips {
+                Some(path) => serve::clips::ClipLibrary::from_json(
+                    utils::fileio::load_json_file(path)?,
+                    TTSKoko::SAMPLE_RATE,
+                )?,
+                None => serve::clips::ClipLibrary::default(),
+            };
+            let mut config = serve::openai::ServerConfig {
+                encoder_retries: args.encoder_retries,
+                webhooks: serve::jobs::WebhookConfig {
+                    allowed_hosts: args.webhook_allow_hosts,
+                    timeout: std::time::Duration::from_millis(args.webhook_timeout_ms),
+                    retries: args.webhook_retries,
+                },
+                expose_fingerprint: args.fingerprint,
+                voice_pool: voice_pool.map(std::sync::Arc::new),
+                voice_fallbacks: voice_fallbacks.map(std::sync::Arc::new),
+                max_chunks: args.max_chunks,
+                debug_endpoints: args.debug_endpoints,
+                default_speed: args.default_speed,
+                admin_token: args.admin_token,
+                silence_retry: args.silence_retry_rms.map(|min_rms| tts::koko::SilenceRetry {
+                    min_rms,
+                    retries: args.silence_retries,
+                }),
+                elongation: args.elongation,
+                exceptions,
+                sentence_splitter: args.sentence_splitter,
+                pauses,
+                eq_presets,
+                mp3_vbr_min_bitrate: args.mp3_min_bitrate,
+                recent_requests: args.recent_requests,
+                report_clipping: args.report_clipping,
+                startup_duration: None,
+                clips: std::sync::Arc::new(clips),
+            };
+            let mut models = serve::models::ModelRegistry::new(tts.clone());
+            if let Some(path) = &args.quantized_model {
+                let quantized = tts.with_model(path)?;
+                models = models.with_quantized(quantized, args.quantized_voices);
+            }
+            if args.fingerprint {
+                models = models.with_fingerprints();
+            }
+            config.startup_duration = Some(started_at.elapsed());
+            let app = serve::openai::create_server(models, config).await;
             let addr = SocketAddr::from(([0, 0, 0, 0], 3000));
-            println!("Starting OpenAI-compatible server on http://localhost:3000");
-            axum::serve(
-                tokio::net::TcpListener::bind(&addr).await?,
-                app.into_make_service(),
-            )
-            .await?;
+            let server = serve::server::ServerBuilder::new(addr).max_connections(args.max_connections);
+            #[cfg(feature = "tls")]
+            let server = match (&args.tls_cert, &args.tls_key) {
+                (Some(cert), Some(key)) => server.tls(cert, key)?,
+                _ => server,
+            };
+            println!("Starting OpenAI-compatible server on {}://localhost:3000", server.scheme());
+            server.serve(app).await?;
             Ok(())
         } else {
             let mut txt = args.text;
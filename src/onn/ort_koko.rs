@@ -9,7 +9,10 @@ use ort::{
 use super::ort_base;
 use ort_base::OrtBase;
 
+type OutputArray = ArrayBase<OwnedRepr<f32>, IxDyn>;
+
 pub struct OrtKoko {
+    model_path: String,
     sess: Option<Session>,
 }
 impl ort_base::OrtBase for OrtKoko {
@@ -23,21 +26,86 @@ impl ort_base::OrtBase for OrtKoko {
 }
 impl OrtKoko {
     pub fn new(model_path: String) -> Result<Self, String> {
-        let mut instance = OrtKoko { sess: None };
+        let mut instance = OrtKoko {
+            model_path: model_path.clone(),
+            sess: None,
+        };
         instance.load_model(model_path)?;
         Ok(instance)
     }
 
+    /// Rebuilds the session from the original model file.
+    /// On failure the previous session (if any) is kept.
+    pub fn reload(&mut self) -> Result<(), String> {
+        self.load_model(self.model_path.clone())
+    }
+
+    /// Checks the shapes of the inputs before they are handed to ONNX Runtime,
+    /// so that malformed requests are rejected without touching the session.
+    pub fn validate_inputs(tokens: &[Vec<i64>], styles: &[Vec<f32>]) -> Result<(), String> {
+        if tokens.is_empty() || tokens[0].is_empty() {
+            return Err("token sequence is empty".to_string());
+        }
+        if tokens.iter().any(|t| t.len() != tokens[0].len()) {
+            return Err("token sequences in a batch must have the same length".to_string());
+        }
+        if styles.is_empty() || styles[0].len() != 256 {
+            return Err("style vector must have 256 elements".to_string());
+        }
+        Ok(())
+    }
+
     pub fn infer(
         &self,
         tokens: Vec<Vec<i64>>,
         styles: Vec<Vec<f32>>,
-    ) -> Result<ArrayBase<OwnedRepr<f32>, IxDyn>, Box<dyn std::error::Error>> {
+        speed: f32,
+    ) -> Result<OutputArray, Box<dyn std::error::Error>> {
+        let outputs = self.run(tokens, styles, speed)?;
+        let output = outputs["audio"]
+            .try_extract_tensor::<f32>()?
+            .into_owned();
+        Ok(output)
+    }
+
+    /// Runs the model and returns both the waveform and the intermediate
+    /// mel-spectrogram, shaped `[batch, n_mels, frames]`.
+    ///
+    /// The stock Kokoro export only has an `audio` output; this needs a model
+    /// exported with the spectrogram fed to the vocoder as an extra `mel` output.
+    #[cfg(feature = "debug-mel")]
+    pub fn infer_mel(
+        &self,
+        tokens: Vec<Vec<i64>>,
+        styles: Vec<Vec<f32>>,
+    ) -> Result<(OutputArray, OutputArray), Box<dyn std::error::Error>> {
+        let has_mel = self
+            .sess
+            .as_ref()
+            .is_some_and(|sess| sess.outputs.iter().any(|o| o.name == "mel"));
+        if !has_mel {
+            return Err("model has no `mel` output; export it with the vocoder input exposed".into());
+        }
+
+        let outputs = self.run(tokens, styles, 1.0)?;
+        let audio = outputs["audio"].try_extract_tensor::<f32>()?.into_owned();
+        let mel = outputs["mel"].try_extract_tensor::<f32>()?.into_owned();
+        Ok((audio, mel))
+    }
+
+    fn run(
+        &self,
+        tokens: Vec<Vec<i64>>,
+        styles: Vec<Vec<f32>>,
+        speed: f32,
+    ) -> Result<SessionOutputs<'_, '_>, Box<dyn std::error::Error>> {
         // inference koko
-        // token, styles, speed
+        // token, styles, speed (1.0 is the natural rate, larger is faster)
         // 1,N 1,256
         // [[0, 56, 51, 142, 156, 69, 63, 3, 16, 61, 4, 16, 156, 51, 4, 16, 62, 77, 156, 51, 86, 5, 0]]
 
+        OrtKoko::validate_inputs(&tokens, &styles)?;
+
         let shape = [tokens.len(), tokens[0].len()];
         let tokens_flat: Vec<i64> = tokens.into_iter().flatten().collect();
         let tokens = Tensor::from_array((shape, tokens_flat))?;
@@ -49,7 +117,7 @@ impl OrtKoko {
         let style = Tensor::from_array((shape_style, style_flat))?;
         let style_value: SessionInputValue = SessionInputValue::Owned(Value::from(style));
 
-        let speed = vec![1.0f32; 1];
+        let speed = vec![speed; 1];
         let speed = Tensor::from_array(([1], speed))?;
         let speed_value: SessionInputValue = SessionInputValue::Owned(Value::from(speed));
 
@@ -60,12 +128,7 @@ impl OrtKoko {
         ];
 
         if let Some(sess) = &self.sess {
-            let outputs: SessionOutputs = sess.run(SessionInputs::from(inputs))?;
-            let output = outputs["audio"]
-                .try_extract_tensor::<f32>()
-                .expect("Failed to extract tensor")
-                .into_owned();
-            Ok(output)
+            Ok(sess.run(SessionInputs::from(inputs))?)
         } else {
             Err("Session is not initialized.".into())
         }
@@ -0,0 +1,77 @@
+use axum::http::{header::AUTHORIZATION, HeaderMap, StatusCode};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+/// Range of speeds accepted from clients, matching the OpenAI speech API.
+pub const SPEED_RANGE: std::ops::RangeInclusive<f32> = 0.25..=4.0;
+
+/// Settings that can be changed through the admin endpoints while the server runs.
+pub struct RuntimeSettings {
+    /// Bits of the `f32` speed used when a request omits `speed`.
+    default_speed: AtomicU32,
+    /// While set, synthesis requests are turned away with `503`.
+    maintenance: AtomicBool,
+}
+
+impl RuntimeSettings {
+    pub fn new(default_speed: f32) -> Self {
+        RuntimeSettings {
+            default_speed: AtomicU32::new(default_speed.to_bits()),
+            maintenance: AtomicBool::new(false),
+        }
+    }
+
+    pub fn default_speed(&self) -> f32 {
+        f32::from_bits(self.default_speed.load(Ordering::Relaxed))
+    }
+
+    pub fn set_default_speed(&self, speed: f32) {
+        self.default_speed.store(speed.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn maintenance(&self) -> bool {
+        self.maintenance.load(Ordering::Relaxed)
+    }
+
+    pub fn set_maintenance(&self, enabled: bool) {
+        self.maintenance.store(enabled, Ordering::Relaxed);
+    }
+}
+
+/// Checks the request's `Authorization: Bearer <token>` header against the admin token.
+/// Without a configured token the admin endpoints are disabled and answer `404`.
+pub fn authorize(admin_token: Option<&str>, headers: &HeaderMap) -> Result<(), (StatusCode, String)> {
+    let Some(expected) = admin_token else {
+        return Err((StatusCode::NOT_FOUND, "Admin endpoints are disabled".to_string()));
+    };
+    let provided = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    match provided {
+        Some(token) if constant_time_eq(token.as_bytes(), expected.as_bytes()) => Ok(()),
+        _ => Err((StatusCode::UNAUTHORIZED, "Invalid admin token".to_string())),
+    }
+}
+
+/// Compares without exiting early, so the response time does not leak the matching prefix.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_authorize() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(authorize(None, &headers).unwrap_err().0, StatusCode::NOT_FOUND);
+        assert_eq!(authorize(Some("secret"), &headers).unwrap_err().0, StatusCode::UNAUTHORIZED);
+
+        headers.insert(AUTHORIZATION, "Bearer wrong".parse().unwrap());
+        assert_eq!(authorize(Some("secret"), &headers).unwrap_err().0, StatusCode::UNAUTHORIZED);
+
+        headers.insert(AUTHORIZATION, "Bearer secret".parse().unwrap());
+        assert!(authorize(Some("secret"), &headers).is_ok());
+    }
+}
@@ -0,0 +1,188 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use futures_util::stream::{self, StreamExt};
+
+use crate::serve::format::AudioFormat;
+
+/// Input of every request unless `--text` is given.
+const DEFAULT_TEXT: &str = "The quick brown fox jumps over the lazy dog.";
+
+/// Latency percentiles included in the report.
+const PERCENTILES: [f64; 3] = [50.0, 90.0, 99.0];
+
+#[derive(clap::Args, Debug)]
+pub struct BenchArgs {
+    #[arg(long = "url", default_value = "http://localhost:3000", help = "Base URL of the running server")]
+    pub url: String,
+
+    #[arg(long = "concurrency", default_value_t = 4, help = "Requests kept in flight at once")]
+    pub concurrency: usize,
+
+    #[arg(long = "requests", default_value_t = 100, help = "Total number of requests")]
+    pub requests: usize,
+
+    #[arg(long = "format", value_enum, default_value_t = AudioFormat::Mp3)]
+    pub format: AudioFormat,
+
+    #[arg(long = "voice", help = "Voice to request; the server's default when absent")]
+    pub voice: Option<String>,
+
+    #[arg(long = "text", default_value = DEFAULT_TEXT)]
+    pub text: String,
+}
+
+/// Results of a load test. Latencies are measured until the response body is fully
+/// received, and only for successful requests.
+#[derive(Debug)]
+pub struct BenchReport {
+    elapsed: Duration,
+    /// Sorted ascending.
+    latencies: Vec<Duration>,
+    /// Failed requests by cause, e.g. `HTTP 503` or `connect`.
+    errors: BTreeMap<String, usize>,
+}
+
+impl BenchReport {
+    fn new(outcomes: Vec<Result<Duration, String>>, elapsed: Duration) -> Self {
+        let mut latencies = Vec::new();
+        let mut errors = BTreeMap::new();
+        for outcome in outcomes {
+            match outcome {
+                Ok(latency) => latencies.push(latency),
+                Err(cause) => *errors.entry(cause).or_insert(0) += 1,
+            }
+        }
+        latencies.sort();
+        BenchReport { elapsed, latencies, errors }
+    }
+
+    pub fn requests(&self) -> usize {
+        self.latencies.len() + self.error_count()
+    }
+
+    pub fn error_count(&self) -> usize {
+        self.errors.values().sum()
+    }
+
+    /// Fraction of requests that failed, from 0.0 to 1.0.
+    pub fn error_rate(&self) -> f64 {
+        match self.requests() {
+            0 => 0.0,
+            requests => self.error_count() as f64 / requests as f64,
+        }
+    }
+
+    /// Successful requests per second of wall-clock time.
+    pub fn throughput(&self) -> f64 {
+        self.latencies.len() as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+
+    /// Nearest-rank percentile of the successful requests' latencies, e.g. 99.0 for p99.
+    pub fn percentile(&self, percentile: f64) -> Option<Duration> {
+        let rank = (percentile / 100.0 * self.latencies.len() as f64).ceil() as usize;
+        self.latencies.get(rank.clamp(1, self.latencies.len().max(1)) - 1).copied()
+    }
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Requests:   {} in {:.2}s", self.requests(), self.elapsed.as_secs_f64())?;
+        write!(f, "Errors:     {} ({:.1}%)", self.error_count(), self.error_rate() * 100.0)?;
+        for (cause, count) in &self.errors {
+            write!(f, ", {} x{}", cause, count)?;
+        }
+        writeln!(f)?;
+        writeln!(f, "Throughput: {:.2} requests/s", self.throughput())?;
+        write!(f, "Latency:   ")?;
+        if self.latencies.is_empty() {
+            return write!(f, " no successful requests");
+        }
+        for percentile in PERCENTILES {
+            if let Some(latency) = self.percentile(percentile) {
+                write!(f, " p{} {:.3}s,", percentile, latency.as_secs_f64())?;
+            }
+        }
+        let max = self.latencies.last().copied().unwrap_or_default();
+        write!(f, " max {:.3}s", max.as_secs_f64())
+    }
+}
+
+/// Sends `args.requests` speech requests to the server, at most `args.concurrency`
+/// at a time, and reports how they went.
+pub async fn run(args: &BenchArgs) -> Result<BenchReport, Box<dyn std::error::Error>> {
+    let client = reqwest::Client::new();
+    let url = format!("{}/v1/audio/speech", args.url.trim_end_matches('/'));
+    let mut request = serde_json::json!({
+        "model": "tts-1",
+        "input": args.text,
+        "response_format": args.format,
+    });
+    if let Some(voice) = &args.voice {
+        request["voice"] = voice.as_str().into();
+    }
+    let body = serde_json::to_vec(&request)?;
+
+    let started = Instant::now();
+    let outcomes = stream::iter(0..args.requests)
+        .map(|_| send(&client, &url, &body))
+        .buffer_unordered(args.concurrency.max(1))
+        .collect()
+        .await;
+    Ok(BenchReport::new(outcomes, started.elapsed()))
+}
+
+/// The latency of one request, or the cause of its failure.
+async fn send(client: &reqwest::Client, url: &str, body: &[u8]) -> Result<Duration, String> {
+    let started = Instant::now();
+    let cause = |e: reqwest::Error| {
+        if e.is_connect() {
+            "connect".to_string()
+        } else if e.is_timeout() {
+            "timeout".to_string()
+        } else {
+            "transport".to_string()
+        }
+    };
+    let response = client
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body.to_vec())
+        .send()
+        .await
+        .map_err(cause)?;
+    let status = response.status();
+    response.bytes().await.map_err(cause)?;
+    if !status.is_success() {
+        return Err(format!("HTTP {}", status.as_u16()));
+    }
+    Ok(started.elapsed())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bench_report() {
+        let mut outcomes: Vec<Result<Duration, String>> =
+            (1..=98).rev().map(|ms| Ok(Duration::from_millis(ms))).collect();
+        outcomes.push(Err("HTTP 503".to_string()));
+        outcomes.push(Err("HTTP 503".to_string()));
+        let report = BenchReport::new(outcomes, Duration::from_secs(2));
+
+        assert_eq!(report.requests(), 100);
+        assert_eq!(report.error_count(), 2);
+        assert!((report.error_rate() - 0.02).abs() < 1e-9);
+        assert!((report.throughput() - 49.0).abs() < 1e-9);
+        assert_eq!(report.percentile(50.0), Some(Duration::from_millis(49)));
+        assert_eq!(report.percentile(99.0), Some(Duration::from_millis(98)));
+        assert_eq!(report.percentile(0.0), Some(Duration::from_millis(1)));
+        assert!(report.to_string().contains("HTTP 503 x2"));
+
+        let failed = BenchReport::new(vec![Err("connect".to_string())], Duration::from_secs(1));
+        assert_eq!(failed.percentile(50.0), None);
+        assert_eq!(failed.error_rate(), 1.0);
+    }
+}
@@ -0,0 +1,118 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::utils::dsp;
+
+/// Sample rates accepted for clips, which are resampled to the model's rate.
+const SUPPORTED_RATES: std::ops::RangeInclusive<u32> = 8000..=192_000;
+
+/// Named audio clips, such as a station jingle, that requests can put before or after
+/// the speech. Loaded once at startup from WAV files, mixed down to mono and
+/// resampled to the output sample rate.
+#[derive(Debug, Default)]
+pub struct ClipLibrary {
+    clips: HashMap<String, Vec<f32>>,
+}
+
+impl ClipLibrary {
+    /// Loads the clips from a JSON object of names and WAV paths, e.g.
+    /// `{"jingle": "clips/jingle.wav"}`.
+    pub fn from_json(value: serde_json::Value, sample_rate: u32) -> Result<Self, String> {
+        let paths: HashMap<String, String> =
+            serde_json::from_value(value).map_err(|e| format!("Invalid clip list: {}", e))?;
+        let clips = paths
+            .into_iter()
+            .map(|(name, path)| {
+                let samples = load_wav(Path::new(&path), sample_rate)
+                    .map_err(|e| format!("Invalid clip {} ({}): {}", name, path, e))?;
+                Ok((name, samples))
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(ClipLibrary { clips })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&[f32]> {
+        self.clips.get(name).map(Vec::as_slice)
+    }
+
+    /// Clip names, sorted.
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.clips.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+}
+
+/// Reads a WAV file as mono samples at `sample_rate`.
+fn load_wav(path: &Path, sample_rate: u32) -> Result<Vec<f32>, String> {
+    let reader = hound::WavReader::open(path).map_err(|e| e.to_string())?;
+    let spec = reader.spec();
+    if !SUPPORTED_RATES.contains(&spec.sample_rate) {
+        return Err(format!("unsupported sample rate {} Hz", spec.sample_rate));
+    }
+    let samples: Vec<f32> = match spec.sample_format {
+        hound::SampleFormat::Float => reader.into_samples::<f32>().collect::<Result<_, _>>(),
+        hound::SampleFormat::Int => {
+            let full_scale = (1u64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .into_samples::<i32>()
+                .map(|sample| sample.map(|sample| sample as f32 / full_scale))
+                .collect::<Result<_, _>>()
+        }
+    }
+    .map_err(|e| e.to_string())?;
+    if samples.is_empty() {
+        return Err("no audio".to_string());
+    }
+    let channels = usize::from(spec.channels.max(1));
+    let mono: Vec<f32> = samples
+        .chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+        .collect();
+    Ok(dsp::resample(&mono, spec.sample_rate, sample_rate))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_wav(path: &Path, channels: u16, sample_rate: u32, samples: &[i16]) {
+        let spec = hound::WavSpec {
+            channels,
+            sample_rate,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(path, spec).unwrap();
+        for &sample in samples {
+            writer.write_sample(sample).unwrap();
+        }
+        writer.finalize().unwrap();
+    }
+
+    #[test]
+    fn test_clip_library() {
+        let dir = std::env::temp_dir().join(format!("kokoros-clips-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let stereo = dir.join("stereo.wav");
+        let low_rate = dir.join("low-rate.wav");
+        write_wav(&stereo, 2, 24000, &[16384, 0, 16384, 0, 16384, 0]);
+        write_wav(&low_rate, 1, 4000, &[0; 40]);
+
+        let clips = ClipLibrary::from_json(
+            serde_json::json!({"jingle": stereo.to_str().unwrap()}),
+            24000,
+        )
+        .unwrap();
+        // Channels are averaged; clips already at the output rate are kept as they are.
+        assert_eq!(clips.get("jingle"), Some(&[0.25f32, 0.25, 0.25][..]));
+        assert_eq!(clips.get("outro"), None);
+        assert_eq!(clips.names(), ["jingle"]);
+
+        let rejected = ClipLibrary::from_json(serde_json::json!({"hum": low_rate.to_str().unwrap()}), 24000);
+        assert!(rejected.unwrap_err().contains("unsupported sample rate"));
+        assert!(ClipLibrary::from_json(serde_json::json!({"gone": dir.join("missing.wav")}), 24000).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
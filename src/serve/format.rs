@@ -0,0 +1,195 @@
+use serde::{Deserialize, Serialize};
+
+/// Shorthand for common container/codec combinations, as in the OpenAI API.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioFormat {
+    #[default]
+    Mp3,
+    Wav,
+    Caf,
+}
+
+/// File format that wraps the encoded audio.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Container {
+    Wav,
+    Caf,
+    /// A bare MPEG audio stream.
+    Mp3,
+}
+
+/// How the samples are encoded.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Codec {
+    /// 32-bit float linear PCM.
+    PcmF32,
+    /// 16-bit integer linear PCM.
+    PcmS16,
+    Mp3,
+}
+
+impl Codec {
+    pub const ALL: [Codec; 3] = [Codec::PcmF32, Codec::PcmS16, Codec::Mp3];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Codec::PcmF32 => "pcm_f32",
+            Codec::PcmS16 => "pcm_s16",
+            Codec::Mp3 => "mp3",
+        }
+    }
+
+    /// Whether this build includes an encoder for the codec. MP3 needs the `mp3` feature.
+    pub fn is_compiled_in(self) -> bool {
+        match self {
+            Codec::PcmF32 | Codec::PcmS16 => true,
+            Codec::Mp3 => cfg!(feature = "mp3"),
+        }
+    }
+}
+
+/// A container and codec combination that the server can produce.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OutputFormat {
+    pub container: Container,
+    pub codec: Codec,
+}
+
+impl OutputFormat {
+    /// Every supported combination, including ones whose codec is not compiled in.
+    pub const ALL: [OutputFormat; 5] = [
+        OutputFormat::new(Container::Mp3, Codec::Mp3),
+        OutputFormat::new(Container::Wav, Codec::PcmF32),
+        OutputFormat::new(Container::Wav, Codec::PcmS16),
+        OutputFormat::new(Container::Caf, Codec::PcmF32),
+        OutputFormat::new(Container::Caf, Codec::PcmS16),
+    ];
+
+    const fn new(container: Container, codec: Codec) -> Self {
+        OutputFormat { container, codec }
+    }
+
+    /// Works out the output format of a request.
+    ///
+    /// An explicit `container` and `codec` take precedence over the `format` shorthand
+    /// (and `wav_float`). If only one of them is given, the other is filled in from the
+    /// shorthand when that makes a supported combination, or with its usual partner.
+    pub fn resolve(
+        format: AudioFormat,
+        wav_float: bool,
+        container: Option<Container>,
+        codec: Option<Codec>,
+    ) -> Result<Self, String> {
+        let pcm = if wav_float { Codec::PcmF32 } else { Codec::PcmS16 };
+        let shorthand = match format {
+            AudioFormat::Mp3 => OutputFormat::new(Container::Mp3, Codec::Mp3),
+            AudioFormat::Wav => OutputFormat::new(Container::Wav, pcm),
+            AudioFormat::Caf => OutputFormat::new(Container::Caf, Codec::PcmF32),
+        };
+
+        let resolved = match (container, codec) {
+            (None, None) => shorthand,
+            (Some(container), Some(codec)) => OutputFormat::new(container, codec),
+            (Some(container), None) => match container {
+                _ if container == shorthand.container => shorthand,
+                Container::Mp3 => OutputFormat::new(container, Codec::Mp3),
+                Container::Wav => OutputFormat::new(container, pcm),
+                Container::Caf => OutputFormat::new(container, Codec::PcmF32),
+            },
+            (None, Some(codec)) => {
+                let with_shorthand = OutputFormat::new(shorthand.container, codec);
+                if with_shorthand.is_supported() {
+                    with_shorthand
+                } else if codec == Codec::Mp3 {
+                    OutputFormat::new(Container::Mp3, codec)
+                } else {
+                    OutputFormat::new(Container::Wav, codec)
+                }
+            }
+        };
+
+        if !resolved.is_supported() {
+            return Err(format!(
+                "codec {} cannot be stored in a {} container",
+                resolved.codec.name(),
+                resolved.extension()
+            ));
+        }
+        Ok(resolved)
+    }
+
+    fn is_supported(self) -> bool {
+        OutputFormat::ALL.contains(&self)
+    }
+
+    /// Whether this build can produce the format.
+    pub fn is_available(self) -> bool {
+        self.codec.is_compiled_in()
+    }
+
+    /// Whether the container can be written before the length of the audio is known.
+    pub fn is_streamable(self) -> bool {
+        matches!(self.container, Container::Wav | Container::Caf)
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self.container {
+            Container::Wav => "audio/wav",
+            Container::Caf => "audio/x-caf",
+            Container::Mp3 => "audio/mpeg",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self.container {
+            Container::Wav => "wav",
+            Container::Caf => "caf",
+            Container::Mp3 => "mp3",
+        }
+    }
+
+    /// A file name that tells the combinations apart, e.g. `clip_pcm_s16.wav`.
+    pub fn file_name(self, stem: &str) -> String {
+        format!("{}_{}.{}", stem, self.codec.name(), self.extension())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_output_format() {
+        let resolve = OutputFormat::resolve;
+        let format = OutputFormat::new;
+
+        // The shorthand alone keeps its existing meaning.
+        assert_eq!(resolve(AudioFormat::Mp3, true, None, None), Ok(format(Container::Mp3, Codec::Mp3)));
+        assert_eq!(resolve(AudioFormat::Wav, false, None, None), Ok(format(Container::Wav, Codec::PcmS16)));
+
+        // One explicit half is completed from the shorthand or its usual partner.
+        assert_eq!(
+            resolve(AudioFormat::Caf, true, None, Some(Codec::PcmS16)),
+            Ok(format(Container::Caf, Codec::PcmS16))
+        );
+        assert_eq!(
+            resolve(AudioFormat::Mp3, true, None, Some(Codec::PcmS16)),
+            Ok(format(Container::Wav, Codec::PcmS16))
+        );
+        assert_eq!(
+            resolve(AudioFormat::Wav, true, Some(Container::Mp3), None),
+            Ok(format(Container::Mp3, Codec::Mp3))
+        );
+
+        // Illegal combinations are rejected.
+        assert!(resolve(AudioFormat::Mp3, true, Some(Container::Wav), Some(Codec::Mp3)).is_err());
+        assert!(resolve(AudioFormat::Mp3, true, Some(Container::Mp3), Some(Codec::PcmF32)).is_err());
+
+        // PCM is always built in, MP3 only with the `mp3` feature.
+        assert!(format(Container::Caf, Codec::PcmS16).is_available());
+        assert_eq!(format(Container::Mp3, Codec::Mp3).is_available(), cfg!(feature = "mp3"));
+    }
+}
@@ -0,0 +1,153 @@
+use reqwest::Url;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    Pending,
+    Running,
+    Succeeded,
+    Failed,
+}
+
+/// Status of an asynchronous synthesis job, as returned by the status endpoint
+/// and POSTed to the job's webhook on completion.
+#[derive(Clone, Serialize)]
+pub struct JobStatus {
+    pub id: String,
+    pub status: JobState,
+    /// Location of the synthesized audio once the job has succeeded.
+    pub file_path: Option<String>,
+    pub error: Option<String>,
+}
+
+/// In-memory registry of submitted jobs.
+#[derive(Default)]
+pub struct JobStore {
+    jobs: Mutex<HashMap<String, JobStatus>>,
+    next_id: AtomicU64,
+}
+
+impl JobStore {
+    /// Registers a new pending job and returns its status.
+    pub fn create(&self) -> JobStatus {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let seq = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let status = JobStatus {
+            id: format!("job_{}_{}", timestamp, seq),
+            status: JobState::Pending,
+            file_path: None,
+            error: None,
+        };
+        self.update(status.clone());
+        status
+    }
+
+    pub fn update(&self, status: JobStatus) {
+        self.jobs.lock().unwrap().insert(status.id.clone(), status);
+    }
+
+    pub fn get(&self, id: &str) -> Option<JobStatus> {
+        self.jobs.lock().unwrap().get(id).cloned()
+    }
+}
+
+/// Webhook delivery settings, fixed at startup.
+#[derive(Clone)]
+pub struct WebhookConfig {
+    /// Hosts that webhooks may be delivered to (subdomains included).
+    /// When empty, webhooks are disabled.
+    pub allowed_hosts: Vec<String>,
+    pub timeout: Duration,
+    /// How many times a failed delivery is retried.
+    pub retries: u32,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        WebhookConfig {
+            allowed_hosts: Vec::new(),
+            timeout: Duration::from_secs(5),
+            retries: 2,
+        }
+    }
+}
+
+impl WebhookConfig {
+    /// Parses a client-supplied webhook URL and checks it against the allowlist.
+    pub fn validate(&self, url: &str) -> Result<Url, String> {
+        if self.allowed_hosts.is_empty() {
+            return Err("Webhooks are not enabled on this server".to_string());
+        }
+        let url = Url::parse(url).map_err(|e| format!("Invalid webhook URL: {}", e))?;
+        if url.scheme() != "http" && url.scheme() != "https" {
+            return Err("Webhook URL must use http or https".to_string());
+        }
+        let host = url.host_str().unwrap_or("").to_ascii_lowercase();
+        let allowed = self.allowed_hosts.iter().any(|allowed| {
+            let allowed = allowed.to_ascii_lowercase();
+            host == allowed || host.ends_with(&format!(".{}", allowed))
+        });
+        if !allowed {
+            return Err(format!("Webhook host is not allowed: {}", host));
+        }
+        Ok(url)
+    }
+}
+
+/// POSTs the job status to the webhook, retrying failed deliveries with a linear backoff.
+pub async fn deliver_webhook(config: &WebhookConfig, url: Url, status: &JobStatus) {
+    let client = match reqwest::Client::builder().timeout(config.timeout).build() {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("Failed to create webhook client: {}", e);
+            return;
+        }
+    };
+    let body = serde_json::to_vec(status).expect("job status serializes");
+
+    for attempt in 0..=config.retries {
+        if attempt > 0 {
+            tokio::time::sleep(Duration::from_millis(500) * attempt).await;
+        }
+        let result = client
+            .post(url.clone())
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body.clone())
+            .send()
+            .await;
+        match result {
+            Ok(resp) if resp.status().is_success() => return,
+            Ok(resp) => eprintln!("Webhook for {} returned {}", status.id, resp.status()),
+            Err(e) => eprintln!("Webhook for {} failed: {}", status.id, e),
+        }
+    }
+    eprintln!("Giving up on webhook for {}", status.id);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_webhook_allowlist() {
+        let config = WebhookConfig {
+            allowed_hosts: vec!["example.com".to_string()],
+            ..WebhookConfig::default()
+        };
+        assert!(config.validate("https://example.com/hook").is_ok());
+        assert!(config.validate("https://api.example.com/hook").is_ok());
+        assert!(config.validate("https://evil-example.com/hook").is_err());
+        assert!(config.validate("ftp://example.com/hook").is_err());
+        assert!(config.validate("not a url").is_err());
+
+        assert!(WebhookConfig::default().validate("https://example.com/hook").is_err());
+    }
+}
@@ -1 +1,12 @@
-pub mod openai;
\ No newline at end of file
+pub mod admin;
+pub mod bench;
+pub mod clips;
+pub mod format;
+pub mod jobs;
+pub mod models;
+pub mod multipart;
+pub mod openai;
+pub mod recent;
+pub mod server;
+pub mod system;
+pub mod voices;
\ No newline at end of file
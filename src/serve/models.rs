@@ -0,0 +1,141 @@
+use crate::tts::koko::TTSKoko;
+use crate::utils::fileio::file_sha256;
+use serde::Serialize;
+use std::collections::HashSet;
+
+/// Quality/speed class of a loaded model file.
+#[derive(Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModelKind {
+    /// Full-precision model: best quality, slower.
+    Full,
+    /// Quantized model: faster and smaller, slightly lower quality.
+    Quantized,
+}
+
+impl ModelKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            ModelKind::Full => "full",
+            ModelKind::Quantized => "quantized",
+        }
+    }
+}
+
+/// Characteristics of a loaded model, as reported by the capabilities endpoint.
+#[derive(Serialize)]
+pub struct ModelInfo {
+    pub name: &'static str,
+    pub kind: ModelKind,
+    pub size_bytes: u64,
+    pub default: bool,
+    /// Voices routed to this model when the request does not name a model.
+    pub voices: Vec<String>,
+}
+
+pub struct ModelVariant {
+    pub kind: ModelKind,
+    pub size_bytes: u64,
+    /// SHA-256 of the model file, if fingerprints were computed at startup.
+    pub sha256: Option<String>,
+    pub tts: TTSKoko,
+}
+
+impl ModelVariant {
+    fn new(kind: ModelKind, tts: TTSKoko) -> Self {
+        let size_bytes = std::fs::metadata(tts.model_path())
+            .map(|m| m.len())
+            .unwrap_or(0);
+        ModelVariant {
+            kind,
+            size_bytes,
+            sha256: None,
+            tts,
+        }
+    }
+}
+
+/// The model files loaded at startup and the rules for dispatching requests to them.
+///
+/// The full model is always present and is the default. An optional quantized model
+/// serves requests whose `model` field is `"quantized"`, or whose voice is registered
+/// as a quantized voice; `"full"` always selects the full model.
+pub struct ModelRegistry {
+    full: ModelVariant,
+    quantized: Option<ModelVariant>,
+    quantized_voices: HashSet<String>,
+}
+
+impl ModelRegistry {
+    pub fn new(tts: TTSKoko) -> Self {
+        ModelRegistry {
+            full: ModelVariant::new(ModelKind::Full, tts),
+            quantized: None,
+            quantized_voices: HashSet::new(),
+        }
+    }
+
+    /// Registers a quantized model, and the voices that use it by default.
+    pub fn with_quantized(mut self, tts: TTSKoko, voices: Vec<String>) -> Self {
+        self.quantized = Some(ModelVariant::new(ModelKind::Quantized, tts));
+        self.quantized_voices = voices.into_iter().collect();
+        self
+    }
+
+    /// Hashes every loaded model file, so responses can carry a fingerprint of the
+    /// exact model that produced them. Done once, as hashing is slow for large files.
+    pub fn with_fingerprints(mut self) -> Self {
+        for variant in std::iter::once(&mut self.full).chain(self.quantized.as_mut()) {
+            match file_sha256(variant.tts.model_path()) {
+                Ok(hash) => variant.sha256 = Some(hash),
+                Err(e) => eprintln!("Failed to hash {}: {}", variant.tts.model_path(), e),
+            }
+        }
+        self
+    }
+
+    /// The full model, which every instance has.
+    pub fn default_model(&self) -> &TTSKoko {
+        &self.full.tts
+    }
+
+    /// Picks the model for a request: an explicit model hint wins, then the voice's class.
+    pub fn select(&self, model_hint: &str, voice: &str) -> &ModelVariant {
+        let Some(quantized) = &self.quantized else {
+            return &self.full;
+        };
+        match model_hint {
+            "full" => &self.full,
+            "quantized" => quantized,
+            _ if self.quantized_voices.contains(voice) => quantized,
+            _ => &self.full,
+        }
+    }
+
+    pub fn describe(&self) -> Vec<ModelInfo> {
+        let all_voices = self.full.tts.voices();
+        let mut infos = vec![ModelInfo {
+            name: self.full.kind.name(),
+            kind: self.full.kind,
+            size_bytes: self.full.size_bytes,
+            default: true,
+            voices: all_voices
+                .iter()
+                .filter(|v| !self.quantized_voices.contains(*v))
+                .cloned()
+                .collect(),
+        }];
+        if let Some(quantized) = &self.quantized {
+            let mut voices: Vec<String> = self.quantized_voices.iter().cloned().collect();
+            voices.sort();
+            infos.push(ModelInfo {
+                name: quantized.kind.name(),
+                kind: quantized.kind,
+                size_bytes: quantized.size_bytes,
+                default: false,
+                voices,
+            });
+        }
+        infos
+    }
+}
@@ -0,0 +1,57 @@
+/// Builds a `multipart/mixed` body, for responses that carry several files at once.
+pub struct Multipart {
+    boundary: String,
+    body: Vec<u8>,
+}
+
+impl Multipart {
+    pub fn new() -> Self {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        Multipart {
+            boundary: format!("kokoros-{:x}", nanos),
+            body: Vec::new(),
+        }
+    }
+
+    /// Appends a part, offered to the client as a file named `filename`.
+    pub fn add_part(&mut self, content_type: &str, filename: &str, data: &[u8]) {
+        self.body.extend_from_slice(
+            format!(
+                "--{}\r\nContent-Type: {}\r\nContent-Disposition: attachment; filename=\"{}\"\r\n\r\n",
+                self.boundary, content_type, filename
+            )
+            .as_bytes(),
+        );
+        self.body.extend_from_slice(data);
+        self.body.extend_from_slice(b"\r\n");
+    }
+
+    /// Closes the body and returns it with the matching Content-Type header value.
+    pub fn finish(mut self) -> (String, Vec<u8>) {
+        self.body
+            .extend_from_slice(format!("--{}--\r\n", self.boundary).as_bytes());
+        (format!("multipart/mixed; boundary={}", self.boundary), self.body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_multipart_layout() {
+        let mut multipart = Multipart::new();
+        multipart.add_part("audio/wav", "clip.wav", b"RIFF");
+        let boundary = multipart.boundary.clone();
+        let (content_type, body) = multipart.finish();
+
+        assert_eq!(content_type, format!("multipart/mixed; boundary={}", boundary));
+        let body = String::from_utf8(body).unwrap();
+        assert!(body.starts_with(&format!("--{}\r\nContent-Type: audio/wav\r\n", boundary)));
+        assert!(body.contains("filename=\"clip.wav\"\r\n\r\nRIFF\r\n"));
+        assert!(body.ends_with(&format!("--{}--\r\n", boundary)));
+    }
+}
@@ -1,20 +1,47 @@
-use crate::tts::koko::TTSKoko;
-use crate::utils::wav::{write_audio_chunk, WavHeader};
-use axum::http::{StatusCode, header::CONTENT_TYPE};
+use crate::serve::admin::{self, RuntimeSettings};
+use crate::serve::format::{AudioFormat, Codec, Container, OutputFormat};
+use crate::serve::jobs::{self, JobState, JobStatus, JobStore, WebhookConfig};
+use crate::serve::models::{ModelInfo, ModelRegistry, ModelVariant};
+use crate::serve::multipart::Multipart;
+use crate::serve::recent::{self, RecentRequests, RequestRecord};
+use crate::serve::system::SystemInfo;
+use crate::serve::clips::ClipLibrary;
+use crate::serve::voices::{VoiceFallbacks, VoicePool};
+use crate::tts::koko::{self, Aside, LanguageSpan, SilenceRetry, TTSKoko, TTSOpts};
+use crate::tts::normalize::{self, ElongationPolicy, Exceptions, LinkPolicy, PauseMap, SentenceSplitter};
+use crate::tts::respell::{self, WordRespelling};
+use crate::tts::voice::VoiceSpec;
+use crate::utils::caf::{CafHeader, CafSampleFormat};
+use crate::utils::dsp::{self, ClipPolicy, ClipStats, DcBlocker, EqBand, Equalizer, SilenceFill};
+use crate::utils::wav::{Bext, WavHeader};
+use axum::http::{HeaderMap, HeaderValue, StatusCode, header::{CONTENT_TYPE, TRAILER}};
 use axum::{
-    extract::State,
+    async_trait,
+    body::{Body, Bytes},
+    extract::{FromRequest, Path, Query, Request, State},
+    middleware::{self, Next},
     response::{IntoResponse, Response},
     routing::{post, get},
     Json, Router,
 };
 use base64::Engine;
+use http_body_util::StreamBody;
+use hyper::body::Frame;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use tower_http::cors::CorsLayer;
+#[cfg(feature = "mp3")]
 use lame::Lame;
+#[cfg(feature = "mp3")]
 use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::sync::Arc;
+#[cfg(feature = "mp3")]
 use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
 // Global Mutex to ensure MP3 encoding is not executed concurrently.
+#[cfg(feature = "mp3")]
 lazy_static! {
     static ref MP3_ENCODER_LOCK: Mutex<()> = Mutex::new(());
 }
@@ -24,80 +51,779 @@ fn default_true() -> bool {
     true
 }
 
-#[derive(Deserialize)]
+/// `response_format` values this build can produce, as reported by the capabilities endpoint.
+fn supported_formats() -> Vec<&'static str> {
+    let mut formats = Vec::new();
+    if Codec::Mp3.is_compiled_in() {
+        formats.push("mp3");
+    }
+    formats.extend(["wav", "caf"]);
+    formats
+}
+
+/// How a finished (non-streamed) synthesis is delivered.
+#[derive(Clone, Copy, PartialEq, Deserialize)]
 #[serde(rename_all = "lowercase")]
-enum AudioFormat {
-    Mp3,
-    Wav,
+enum Delivery {
+    /// The encoded audio as the response body.
+    Binary,
+    /// Written to the server's disk, with the path in a JSON response.
+    File,
+    /// Base64 in the `audio` field of a JSON response, without touching the disk.
+    Inline,
 }
 
-impl Default for AudioFormat {
-    fn default() -> Self {
-        AudioFormat::Mp3
+/// Level and length of the calibration tone when the request omits them.
+const DEFAULT_CALIBRATION_LEVEL_DBFS: f32 = -18.0;
+const DEFAULT_CALIBRATION_DURATION_MS: u32 = 1000;
+/// Longest calibration tone a request may ask for, in milliseconds.
+const MAX_CALIBRATION_DURATION_MS: u32 = 60_000;
+/// Frequency of the calibration tone, in Hz.
+const CALIBRATION_FREQUENCY: f32 = 1000.0;
+
+fn default_calibration_level() -> f32 {
+    DEFAULT_CALIBRATION_LEVEL_DBFS
+}
+
+fn default_calibration_duration() -> u32 {
+    DEFAULT_CALIBRATION_DURATION_MS
+}
+
+/// A 1 kHz sine at an exact level, prepended to the speech so that downstream level
+/// meters can be aligned. It is added after all processing, so the clip policy, EQ
+/// and DC removal leave its level untouched.
+#[derive(Clone, Copy, Deserialize)]
+struct CalibrationTone {
+    /// Peak level in dBFS.
+    #[serde(default = "default_calibration_level")]
+    level_dbfs: f32,
+    #[serde(default = "default_calibration_duration")]
+    duration_ms: u32,
+}
+
+impl CalibrationTone {
+    fn samples(&self) -> Vec<f32> {
+        dsp::sine_tone(CALIBRATION_FREQUENCY, self.level_dbfs, self.duration_ms, TTSKoko::SAMPLE_RATE)
+    }
+}
+
+/// Ceilings the soft limiter accepts, in dBFS.
+const LIMITER_CEILING_RANGE: std::ops::RangeInclusive<f32> = -20.0..=0.0;
+
+/// Silence between an intro or outro clip and the speech when the request omits it.
+const DEFAULT_CLIP_GAP_MS: u32 = 300;
+/// Longest gap a request may ask for, in milliseconds.
+const MAX_CLIP_GAP_MS: u32 = 10_000;
+
+/// Bitrate of MP3 previews when the request does not give one, in kbit/s.
+const DEFAULT_PREVIEW_KILOBITRATE: u32 = 32;
+/// Bitrates of MPEG-2 Layer III, which is what LAME writes at the model's 24 kHz, in kbit/s.
+pub const MP3_KILOBITRATES: [u32; 14] = [8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160];
+
+/// Encoder settings that differ from LAME's defaults.
+#[derive(Clone, Copy, Debug, Default)]
+struct Mp3Settings {
+    /// Constant bitrate in kbit/s.
+    kilobitrate: Option<u32>,
+    /// Encode VBR, never dropping below this bitrate in kbit/s, not even for silence.
+    vbr_min_kilobitrate: Option<u32>,
+}
+
+fn default_preview_kilobitrate() -> u32 {
+    DEFAULT_PREVIEW_KILOBITRATE
+}
+
+/// A lower-quality copy of the audio returned next to the requested format.
+#[derive(Clone, Copy, Deserialize)]
+struct LowBitratePreview {
+    /// `wav` and `caf` previews are 16-bit PCM.
+    #[serde(default)]
+    format: AudioFormat,
+    /// MP3 bitrate in kbit/s.
+    #[serde(default = "default_preview_kilobitrate")]
+    bitrate: u32,
+}
+
+impl LowBitratePreview {
+    fn output_format(&self) -> Result<OutputFormat, HandlerError> {
+        let output = OutputFormat::resolve(self.format, false, None, None)
+            .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+        if !output.is_available() {
+            return Err((
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                format!("Codec {} is not available in this build", output.codec.name()),
+            ));
+        }
+        Ok(output)
     }
 }
 
 #[derive(Deserialize)]
 struct TTSRequest {
-    #[allow(dead_code)]
+    /// `"full"` or `"quantized"` selects a model explicitly; other values (e.g. `"tts-1"`)
+    /// leave the choice to the voice's configured model class.
     model: String,
     input: String,
     voice: Option<String>,
     #[serde(default = "default_true")]
     return_audio: bool,
+    /// How the audio is delivered, overriding `return_audio`.
+    delivery: Option<Delivery>,
+    /// Include a readable respelling of each word (e.g. "tuh-MAY-toh") in the JSON
+    /// response, for pronunciation teaching. Needs `file` or `inline` delivery.
+    #[serde(default)]
+    respelling: bool,
+    /// Also return a small copy of the audio, e.g. `{"format": "mp3", "bitrate": 32}`,
+    /// encoded from the same synthesis. Not available when streaming.
+    low_bitrate_preview: Option<LowBitratePreview>,
+    /// Shorthand for a container and codec; see `container` and `codec`.
     #[serde(default)]
     response_format: AudioFormat,
+    /// Container the audio is delivered in, overriding `response_format`.
+    container: Option<Container>,
+    /// Codec the audio is encoded with, overriding `response_format`.
+    codec: Option<Codec>,
+    /// How out-of-range samples are handled before encoding: `clamp`, `soft_limit` or `normalize`.
+    /// When streaming, `normalize` is applied to each chunk separately.
+    #[serde(default)]
+    clip_policy: ClipPolicy,
+    /// Level in dBFS that `soft_limit` keeps sample peaks under, from -20 to 0.
+    /// -1 dBFS when absent.
+    limiter_ceiling_dbfs: Option<f32>,
+    /// Remove DC offset with a high-pass filter before encoding.
+    #[serde(default)]
+    remove_dc: bool,
+    /// Extra pause inserted at commas, in milliseconds. Off when absent.
+    comma_pause_ms: Option<u32>,
+    /// What inserted pauses are made of: `zero` (digital silence) or `dither` (a faint noise floor).
+    #[serde(default)]
+    silence_fill: SilenceFill,
+    /// How URLs and email addresses are read: `spoken`, `summarize` or `omit`.
+    #[serde(default)]
+    link_policy: LinkPolicy,
+    /// Read `<say-as interpret-as="characters|ordinal|telephone">` elements in the input
+    /// accordingly, e.g. for confirmation codes and phone numbers.
+    #[serde(default)]
+    say_as: bool,
+    /// How elongations like "soooo" are read: `collapse` or `literal`.
+    /// The server's default is used when absent.
+    elongation: Option<ElongationPolicy>,
+    /// Synthesize only the first this many words of the input, e.g. for short excerpts.
+    /// The number of words dropped is reported in a header.
+    max_words: Option<usize>,
+    /// Return only about the first this many seconds, and skip synthesizing the rest,
+    /// for quick iteration on voices and prompts.
+    preview_seconds: Option<f32>,
+    /// Phonemize text in other scripts (e.g. Cyrillic, Greek, kana) with the matching
+    /// language instead of English. The detected spans are reported in a header.
+    #[serde(default)]
+    mixed_language: bool,
+    /// Send the audio chunk by chunk as it is synthesized (`wav` and `caf` only).
+    #[serde(default)]
+    stream: bool,
+    /// When streaming, synthesize this many leading words first to cut the time to first audio.
+    /// Rejected without `stream`, where it would only break up the first sentence.
+    first_chunk_words: Option<usize>,
+    /// Synthesize inputs of one or two words (e.g. "Done") after a carrier phrase that is
+    /// cut off afterwards, for cleaner short utterances.
+    #[serde(default)]
+    pad_short_input: bool,
+    /// End input that lacks terminal punctuation with a full stop in the input's
+    /// language, for a natural ending instead of a trailing-off or abrupt one.
+    #[serde(default)]
+    terminal_punctuation: bool,
+    /// Vary prosody and pace randomly by up to this much, from 0.0 to 1.0, so that
+    /// repeats of the same text differ subtly. Output is deterministic when absent.
+    variation: Option<f32>,
+    /// Speaking rate from 0.25 to 4.0. The server's default speed is used when absent.
+    speed: Option<f32>,
+    /// Fit the speech into this many seconds by choosing the speed, e.g. for a video slot.
+    /// Cannot be combined with `speed` or `stream`.
+    target_duration: Option<f32>,
+    /// Read text in parentheses as a quieter aside, e.g. `{"gain": 0.6, "speed": 1.1}`.
+    /// Off when absent.
+    aside: Option<Aside>,
+    /// Write WAV as 32-bit float (the default) or, when false, as 16-bit integer PCM.
+    /// Shorthand for the `pcm_f32` and `pcm_s16` codecs.
+    #[serde(default = "default_true")]
+    wav_float: bool,
+    /// Add a `PEAK` chunk with the peak level and position to float WAV output.
+    /// Rejected for other output and when streaming.
+    #[serde(default)]
+    wav_peak: bool,
+    /// Make WAV output Broadcast Wave Format with a `bext` chunk, e.g.
+    /// `{"description": "Evening news", "originator": "Newsroom"}`. Rejected for other output.
+    bext: Option<Bext>,
+    /// Name of a server-side clip played before the speech, e.g. a jingle.
+    intro: Option<String>,
+    /// Name of a server-side clip played after the speech.
+    outro: Option<String>,
+    /// Silence between the speech and the intro or outro, in milliseconds.
+    clip_gap_ms: Option<u32>,
+    /// Start the audio with a 1 kHz reference tone for calibrating levels, e.g.
+    /// `{"level_dbfs": -18, "duration_ms": 1000}`. Off when absent.
+    calibration_tone: Option<CalibrationTone>,
+    /// Report the sample offset at which each synthesis chunk starts, for clients that
+    /// crossfade or seek by chunk. Sent as a header, or as a trailer when streaming.
+    #[serde(default)]
+    chunk_boundaries: bool,
+    /// Identifies the end user. With a hash voice pool, the same user always gets the same voice.
+    user: Option<String>,
+    /// The voice the client asked for, when it was unavailable and a fallback replaced it.
+    #[serde(skip)]
+    replaced_voice: Option<String>,
+    /// How many words `max_words` cut from the input; `None` without `max_words`.
+    #[serde(skip)]
+    words_dropped: Option<usize>,
+}
+
+impl TTSRequest {
+    fn wav_chunks(&self) -> WavChunks<'_> {
+        WavChunks {
+            peak: self.wav_peak,
+            bext: self.bext.as_ref(),
+        }
+    }
+
+    /// `return_audio` picks between `binary` and `file` unless `delivery` is given.
+    fn delivery(&self) -> Delivery {
+        self.delivery.unwrap_or(if self.return_audio {
+            Delivery::Binary
+        } else {
+            Delivery::File
+        })
+    }
+
+    fn output_format(&self) -> Result<OutputFormat, HandlerError> {
+        let output =
+            OutputFormat::resolve(self.response_format, self.wav_float, self.container, self.codec)
+                .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+        if !output.is_available() {
+            return Err((
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                format!("Codec {} is not available in this build", output.codec.name()),
+            ));
+        }
+        Ok(output)
+    }
+}
+
+/// Request body for an asynchronous synthesis job: a regular speech request plus
+/// an optional webhook notified on completion.
+#[derive(Deserialize)]
+struct JobRequest {
+    #[serde(flatten)]
+    request: TTSRequest,
+    webhook_url: Option<String>,
+}
+
+/// Content types under which a request body is decoded as MessagePack instead of JSON.
+const MSGPACK_CONTENT_TYPES: [&str; 2] = ["application/msgpack", "application/x-msgpack"];
+
+/// Extracts a request, such as a `TTSRequest`, from either a JSON body (the default)
+/// or a MessagePack body, selected by the request's Content-Type.
+struct RequestBody<T>(T);
+
+#[async_trait]
+impl<S: Send + Sync, T: DeserializeOwned> FromRequest<S> for RequestBody<T> {
+    type Rejection = Response;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let is_msgpack = req
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.split(';').next().unwrap_or("").trim())
+            .is_some_and(|mime| MSGPACK_CONTENT_TYPES.contains(&mime));
+
+        if is_msgpack {
+            let body = Bytes::from_request(req, state)
+                .await
+                .map_err(IntoResponse::into_response)?;
+            let payload = rmp_serde::from_slice(&body).map_err(|e| {
+                (StatusCode::BAD_REQUEST, format!("Invalid MessagePack body: {}", e)).into_response()
+            })?;
+            Ok(RequestBody(payload))
+        } else {
+            let Json(payload) = Json::from_request(req, state)
+                .await
+                .map_err(IntoResponse::into_response)?;
+            Ok(RequestBody(payload))
+        }
+    }
+}
+
+/// Response of the capabilities endpoint.
+#[derive(Serialize)]
+struct CapabilitiesResponse {
+    models: Vec<ModelInfo>,
+    voices: Vec<String>,
+    formats: Vec<&'static str>,
+    /// Codecs compiled into this build.
+    codecs: Vec<&'static str>,
 }
 
 #[derive(Serialize)]
 struct TTSResponse {
     status: String,
     file_path: Option<String>, // Present when the audio is written to a file.
-    audio: Option<String>,     // Can be used if you need to return base64 encoded audio.
+    audio: Option<String>,     // Base64 of the encoded audio with inline delivery.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    respelling: Option<Vec<WordRespelling>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    preview_file_path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    preview_audio: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fingerprint: Option<Fingerprint>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duration_fit: Option<DurationFit>,
+}
+
+/// How a request with a `target_duration` was fitted.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+struct DurationFit {
+    /// Actual duration of the audio, in seconds, including any calibration tone, intro and outro.
+    duration: f32,
+    speed: f32,
+    /// Whether the duration is within 5% of the target. It is not when the speed
+    /// needed is outside the supported range.
+    target_met: bool,
+}
+
+/// Identifies exactly what produced a response, for reproducing problem reports.
+/// Only sent when the server is started with fingerprints enabled.
+#[derive(Clone, Serialize)]
+struct Fingerprint {
+    model: &'static str,
+    model_sha256: Option<String>,
+    voice: String,
+    version: &'static str,
+    clip_policy: ClipPolicy,
+    comma_pause_ms: Option<u32>,
+    speed: Option<f32>,
+}
+
+impl Fingerprint {
+    /// Renders the fingerprint as `key=value` pairs for the `X-Kokoros-Fingerprint` header.
+    fn to_header_value(&self) -> String {
+        let optional = |v: Option<String>| v.unwrap_or_else(|| "none".to_string());
+        format!(
+            "model={}; model_sha256={}; voice={}; version={}; clip_policy={}; comma_pause_ms={}; speed={}",
+            self.model,
+            optional(self.model_sha256.clone()),
+            self.voice,
+            self.version,
+            serde_json::to_value(self.clip_policy)
+                .ok()
+                .and_then(|v| v.as_str().map(str::to_string))
+                .unwrap_or_default(),
+            optional(self.comma_pause_ms.map(|ms| ms.to_string())),
+            optional(self.speed.map(|speed| speed.to_string())),
+        )
+    }
+}
+
+/// Synthesized audio together with the metadata reported back to the client.
+struct Synthesis {
+    audio: Vec<f32>,
+    voice: String,
+    replaced_voice: Option<String>,
+    fingerprint: Option<Fingerprint>,
+    duration_fit: Option<DurationFit>,
+    language_spans: Option<HeaderValue>,
+    preview_seconds: Option<HeaderValue>,
+    /// Present when the server reports clipping.
+    clip_stats: Option<ClipStats>,
+    /// Present when the request asks for chunk boundaries.
+    chunk_offsets: Option<Vec<usize>>,
+    /// Present when the request sets `max_words`.
+    words_dropped: Option<usize>,
+}
+
+impl Synthesis {
+    /// Response headers carrying this synthesis' metadata.
+    fn headers(&self) -> HeaderMap {
+        let mut headers = metadata_headers(
+            &self.voice,
+            self.replaced_voice.as_deref(),
+            self.fingerprint.as_ref(),
+        );
+        if let Some(spans) = &self.language_spans {
+            headers.insert("x-kokoros-language-spans", spans.clone());
+        }
+        if let Some(seconds) = &self.preview_seconds {
+            headers.insert("x-kokoros-preview-seconds", seconds.clone());
+        }
+        if let Some(fit) = self.duration_fit {
+            let number = |value: f32| format!("{:.3}", value).parse().expect("valid header value");
+            headers.insert("x-kokoros-duration", number(fit.duration));
+            headers.insert("x-kokoros-speed", number(fit.speed));
+            headers.insert(
+                "x-kokoros-target-met",
+                HeaderValue::from_static(if fit.target_met { "true" } else { "false" }),
+            );
+        }
+        if let Some(stats) = self.clip_stats {
+            insert_clip_headers(&mut headers, stats);
+        }
+        if let Some(offsets) = &self.chunk_offsets {
+            headers.insert(CHUNK_OFFSETS_HEADER, chunk_offsets_header(offsets));
+        }
+        if let Some(dropped) = self.words_dropped {
+            insert_truncation_headers(&mut headers, dropped);
+        }
+        headers
+    }
+}
+
+/// Reports whether `max_words` cut the input, and by how many words.
+fn insert_truncation_headers(headers: &mut HeaderMap, words_dropped: usize) {
+    headers.insert(
+        "x-kokoros-truncated",
+        HeaderValue::from_static(if words_dropped > 0 { "true" } else { "false" }),
+    );
+    headers.insert("x-kokoros-words-dropped", words_dropped.into());
+}
+
+/// Lists the sample offset at which each synthesis chunk starts, e.g. `0, 48000, 91200`.
+const CHUNK_OFFSETS_HEADER: &str = "x-kokoros-chunk-offsets";
+
+fn chunk_offsets_header(offsets: &[usize]) -> HeaderValue {
+    let offsets: Vec<String> = offsets.iter().map(usize::to_string).collect();
+    offsets.join(", ").parse().expect("valid header value")
+}
+
+fn insert_clip_headers(headers: &mut HeaderMap, stats: ClipStats) {
+    headers.insert(
+        "x-kokoros-clipped",
+        HeaderValue::from_static(if stats.clipped() { "true" } else { "false" }),
+    );
+    headers.insert("x-kokoros-clipped-samples", stats.clipped_samples.into());
+    headers.insert(
+        "x-kokoros-peak",
+        format!("{:.3}", stats.peak).parse().expect("valid header value"),
+    );
+}
+
+/// Trailers sent after a streamed body, since the totals are unknown until then.
+/// Clipping trailers are only sent when the server reports clipping.
+const STREAM_TRAILERS: [&str; 2] = ["x-kokoros-duration", "x-kokoros-samples"];
+const CLIP_TRAILERS: [&str; 3] = ["x-kokoros-clipped", "x-kokoros-clipped-samples", "x-kokoros-peak"];
+
+/// Final metadata of a stream that produced `samples` samples.
+fn stream_trailers(
+    samples: usize,
+    clip_stats: Option<ClipStats>,
+    chunk_offsets: Option<&[usize]>,
+) -> HeaderMap {
+    let mut trailers = HeaderMap::new();
+    trailers.insert(
+        "x-kokoros-duration",
+        format!("{:.3}", duration_of_samples(samples)).parse().expect("valid header value"),
+    );
+    trailers.insert("x-kokoros-samples", samples.into());
+    if let Some(stats) = clip_stats {
+        insert_clip_headers(&mut trailers, stats);
+    }
+    if let Some(offsets) = chunk_offsets {
+        trailers.insert(CHUNK_OFFSETS_HEADER, chunk_offsets_header(offsets));
+    }
+    trailers
+}
+
+/// Reports the voice used (which may have been picked from the voice pool), the
+/// requested voice it replaced if a fallback was used, and, if enabled, the fingerprint.
+fn metadata_headers(
+    voice: &str,
+    replaced_voice: Option<&str>,
+    fingerprint: Option<&Fingerprint>,
+) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    if let Ok(value) = HeaderValue::from_str(voice) {
+        headers.insert("x-kokoros-voice", value);
+    }
+    if let Some(Ok(value)) = replaced_voice.map(HeaderValue::from_str) {
+        headers.insert("x-kokoros-voice-replaced", value);
+    }
+    if let Some(fingerprint) = fingerprint {
+        if let Ok(value) = HeaderValue::from_str(&fingerprint.to_header_value()) {
+            headers.insert("x-kokoros-fingerprint", value);
+        }
+    }
+    headers
+}
+
+/// Server-wide settings, fixed at startup.
+#[derive(Clone)]
+pub struct ServerConfig {
+    /// How many times a transient encoder failure is retried before giving up.
+    pub encoder_retries: u32,
+    pub webhooks: WebhookConfig,
+    /// Whether responses carry a fingerprint of the model, voice, version and settings used.
+    /// Off by default so internals are not exposed publicly.
+    pub expose_fingerprint: bool,
+    /// Voices picked from for requests that do not name a voice.
+    pub voice_pool: Option<Arc<VoicePool>>,
+    /// Substitutes for voices that are unavailable; no substitution when `None`.
+    pub voice_fallbacks: Option<Arc<VoiceFallbacks>>,
+    /// Upper bound on the chunks a request is synthesized in; see `TTSOpts::max_chunks`.
+    pub max_chunks: Option<usize>,
+    /// Whether debugging endpoints such as `/debug/formats` are served.
+    pub debug_endpoints: bool,
+    /// Speed used when a request omits `speed`, until changed through the admin endpoint.
+    pub default_speed: f32,
+    /// Bearer token for the `/admin` endpoints, which are disabled without one.
+    pub admin_token: Option<String>,
+    /// Re-synthesis of chunks that come out silent; off when `None`.
+    pub silence_retry: Option<SilenceRetry>,
+    /// Elongation handling for requests that do not choose one.
+    pub elongation: ElongationPolicy,
+    /// Patterns that text normalization leaves verbatim or spells as configured.
+    pub exceptions: Arc<Exceptions>,
+    pub sentence_splitter: SentenceSplitter,
+    /// Pauses after punctuation; off when `None`.
+    pub pauses: Option<Arc<PauseMap>>,
+    /// EQ applied to each voice's output, by voice name. Voices without a preset are left as is.
+    pub eq_presets: HashMap<String, Vec<EqBand>>,
+    /// Encode MP3 as VBR that never drops below this bitrate in kbit/s. LAME's constant
+    /// bitrate is used when `None`.
+    pub mp3_vbr_min_bitrate: Option<u32>,
+    /// How many recent requests `/admin/recent-requests` lists; 0 disables it.
+    pub recent_requests: usize,
+    /// Whether responses report if samples had to be brought back into range.
+    pub report_clipping: bool,
+    /// How long loading the models and voices took, reported by `/v1/system`.
+    pub startup_duration: Option<Duration>,
+    /// Intro and outro clips that requests can select by name.
+    pub clips: Arc<ClipLibrary>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            encoder_retries: 2,
+            webhooks: WebhookConfig::default(),
+            expose_fingerprint: false,
+            voice_pool: None,
+            voice_fallbacks: None,
+            max_chunks: None,
+            debug_endpoints: false,
+            default_speed: 1.0,
+            admin_token: None,
+            silence_retry: None,
+            elongation: ElongationPolicy::default(),
+            exceptions: Arc::default(),
+            sentence_splitter: SentenceSplitter::default(),
+            pauses: None,
+            eq_presets: HashMap::new(),
+            mp3_vbr_min_bitrate: None,
+            recent_requests: recent::DEFAULT_CAPACITY,
+            report_clipping: false,
+            startup_duration: None,
+            clips: Arc::default(),
+        }
+    }
+}
+
+/// Shared state handed to every handler.
+#[derive(Clone)]
+struct AppState {
+    models: Arc<ModelRegistry>,
+    config: Arc<ServerConfig>,
+    jobs: Arc<JobStore>,
+    settings: Arc<RuntimeSettings>,
+    recent: Arc<RecentRequests>,
+    started_at: Instant,
 }
 
 /// Creates and configures the router for our API, adding both our TTS endpoint and a health check endpoint.
-pub async fn create_server(tts: TTSKoko) -> Router {
-    Router::new()
+pub async fn create_server(models: ModelRegistry, config: ServerConfig) -> Router {
+    let state = AppState {
+        models: Arc::new(models),
+        settings: Arc::new(RuntimeSettings::new(config.default_speed)),
+        recent: Arc::new(RecentRequests::new(config.recent_requests)),
+        config: Arc::new(config),
+        jobs: Arc::new(JobStore::default()),
+        started_at: Instant::now(),
+    };
+
+    // Endpoints that run synthesis, which are turned away in maintenance mode.
+    let mut synthesis = Router::new()
         .route("/v1/audio/speech", post(handle_tts))
+        .route("/v1/audio/speech/jobs", post(handle_create_job));
+    if state.config.debug_endpoints {
+        synthesis = synthesis
+            .route("/debug/formats", post(handle_debug_formats))
+            .route("/debug/samples", post(handle_debug_samples));
+    }
+    #[cfg(feature = "debug-mel")]
+    let synthesis = synthesis.route("/debug/mel", post(handle_debug_mel));
+    let synthesis = synthesis.route_layer(middleware::from_fn_with_state(
+        state.clone(),
+        reject_during_maintenance,
+    ));
+
+    Router::new()
+        .merge(synthesis)
+        .route("/v1/audio/speech/jobs/:id", get(handle_get_job))
+        .route("/v1/capabilities", get(handle_capabilities))
+        .route("/v1/system", get(handle_system))
+        .route(
+            "/admin/default-speed",
+            get(handle_get_default_speed).put(handle_set_default_speed),
+        )
+        .route(
+            "/admin/maintenance",
+            get(handle_get_maintenance).put(handle_set_maintenance),
+        )
+        .route("/admin/recent-requests", get(handle_recent_requests))
         .route("/health", get(handle_health))
         .layer(CorsLayer::permissive())
-        .with_state(tts)
+        .with_state(state)
+}
+
+/// Seconds clients are asked to wait before retrying while in maintenance mode.
+const MAINTENANCE_RETRY_AFTER_SECS: u32 = 30;
+
+/// Answers synthesis requests with `503 Service Unavailable` while maintenance mode is on.
+async fn reject_during_maintenance(
+    State(state): State<AppState>,
+    req: Request,
+    next: Next,
+) -> Response {
+    if state.settings.maintenance() {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            [("retry-after", MAINTENANCE_RETRY_AFTER_SECS.to_string())],
+            "Server is in maintenance mode, retry later",
+        )
+            .into_response();
+    }
+    next.run(req).await
+}
+
+/// Base delay between encoder retries; grows linearly with each attempt.
+const ENCODER_RETRY_BACKOFF: Duration = Duration::from_millis(10);
+
+/// An encoder failure, classified by whether retrying the same input might help.
+#[derive(Debug)]
+enum EncoderError {
+    /// Resource exhaustion (e.g. out of memory) that may clear up on its own.
+    #[cfg_attr(not(feature = "mp3"), allow(dead_code))]
+    Transient(String),
+    /// Bad parameters or input; retrying would fail the same way.
+    Fatal(String),
+}
+
+impl std::fmt::Display for EncoderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EncoderError::Transient(msg) => write!(f, "transient encoder error: {}", msg),
+            EncoderError::Fatal(msg) => write!(f, "encoder error: {}", msg),
+        }
+    }
+}
+
+#[cfg(feature = "mp3")]
+fn lame_error(context: &str, e: lame::Error) -> EncoderError {
+    match e {
+        lame::Error::NoMem => EncoderError::Transient(format!("{}: out of memory", context)),
+        e => EncoderError::Fatal(format!("{}: {:?}", context, e)),
+    }
+}
+
+/// Runs `encode`, retrying up to `retries` times with a small backoff while it
+/// fails transiently. Fatal failures are returned immediately.
+fn encode_with_retries<T>(
+    retries: u32,
+    mut encode: impl FnMut() -> Result<T, EncoderError>,
+) -> Result<T, EncoderError> {
+    let mut attempt = 0;
+    loop {
+        match encode() {
+            Err(EncoderError::Transient(msg)) if attempt < retries => {
+                attempt += 1;
+                eprintln!("Encoder failed transiently ({}), retry {}/{}", msg, attempt, retries);
+                std::thread::sleep(ENCODER_RETRY_BACKOFF * attempt);
+            }
+            result => return result,
+        }
+    }
 }
 
 // Add our own FFI bindings for LAME's flush function.
 // We define a dummy type for the underlying C type.
+#[cfg(feature = "mp3")]
 #[repr(C)]
 struct LameGlobalFlags {
     _private: [u8; 0],
 }
 
 // Alias for the LAME handle.
+#[cfg(feature = "mp3")]
 type LameT = LameGlobalFlags;
 
+#[cfg(feature = "mp3")]
 extern "C" {
     // Declaration for the native function:
     // int lame_encode_flush(lame_t *gfp, unsigned char *mp3buf, int size);
     fn lame_encode_flush(lame: *mut LameT, mp3buf: *mut u8, size: i32) -> i32;
+    // int lame_set_VBR(lame_t gfp, vbr_mode mode);
+    fn lame_set_VBR(lame: *mut LameT, mode: i32) -> i32;
+    // int lame_set_VBR_min_bitrate_kbps(lame_t gfp, int kbps);
+    fn lame_set_VBR_min_bitrate_kbps(lame: *mut LameT, kbps: i32) -> i32;
+    // int lame_set_VBR_hard_min(lame_t gfp, int enforce);
+    fn lame_set_VBR_hard_min(lame: *mut LameT, enforce: i32) -> i32;
 }
 
-/// Custom flush helper using FFI.
-///
-/// This accesses (via an unsafe cast) the underlying raw pointer of the Lame instance,
-/// then calls the FFI flush function.
-fn flush_lame(lame: &mut Lame, flush_buffer: &mut [u8]) -> Result<usize, StatusCode> {
-    let lame_ptr = unsafe {
+/// `vbr_default` from LAME's `vbr_mode` enum.
+#[cfg(feature = "mp3")]
+const LAME_VBR_DEFAULT: i32 = 4;
+
+/// The raw LAME handle behind a `Lame`, which the crate does not expose.
+#[cfg(feature = "mp3")]
+fn lame_handle(lame: &mut Lame) -> *mut LameT {
+    unsafe {
         // Cast the Lame instance to a pointer to a pointer of LameT.
         let ptr_ptr: *const *mut LameT = lame as *const _ as *const *mut LameT;
         *ptr_ptr
+    }
+}
+
+/// Switches the encoder to VBR that never drops below `min_kilobitrate`, including for
+/// digital silence, which LAME otherwise encodes at the lowest bitrate regardless.
+/// Must be called before `init_params`.
+#[cfg(feature = "mp3")]
+fn set_vbr_floor(lame: &mut Lame, min_kilobitrate: u32) -> Result<(), EncoderError> {
+    let handle = lame_handle(lame);
+    let codes = unsafe {
+        [
+            lame_set_VBR(handle, LAME_VBR_DEFAULT),
+            lame_set_VBR_min_bitrate_kbps(handle, min_kilobitrate as i32),
+            lame_set_VBR_hard_min(handle, 1),
+        ]
     };
+    match codes.into_iter().find(|&code| code != 0) {
+        Some(code) => Err(EncoderError::Fatal(format!("set VBR floor failed with code {}", code))),
+        None => Ok(()),
+    }
+}
+
+/// Custom flush helper using FFI.
+///
+/// This accesses the underlying raw pointer of the Lame instance, then calls the FFI
+/// flush function.
+#[cfg(feature = "mp3")]
+fn flush_lame(lame: &mut Lame, flush_buffer: &mut [u8]) -> Result<usize, EncoderError> {
+    let lame_ptr = lame_handle(lame);
 
     let flush_len = unsafe {
         lame_encode_flush(lame_ptr, flush_buffer.as_mut_ptr(), flush_buffer.len() as i32)
     };
 
     if flush_len < 0 {
-        Err(StatusCode::INTERNAL_SERVER_ERROR)
+        Err(EncoderError::Fatal(format!("flush failed with code {}", flush_len)))
     } else {
         Ok(flush_len as usize)
     }
@@ -106,27 +832,39 @@ fn flush_lame(lame: &mut Lame, flush_buffer: &mut [u8]) -> Result<usize, StatusC
 /// Converts raw audio samples (f32) to MP3-encoded bytes.
 /// For MP3 encoding, we initialize LAME with 2 channels—even though our audio is mono—and supply
 /// identical PCM data for both left and right channels.
-fn encode_to_mp3(raw_audio: &[f32]) -> Result<Vec<u8>, StatusCode> {
+#[cfg(feature = "mp3")]
+fn encode_to_mp3(raw_audio: &[f32], settings: Mp3Settings) -> Result<Vec<u8>, EncoderError> {
     // Lock to ensure this section is executed by only one thread at a time.
     let _lock = MP3_ENCODER_LOCK.lock().unwrap();
 
-    let mut lame = Lame::new().expect("Failed to initialize LAME");
+    // LAME only fails to allocate its context when memory is short.
+    let mut lame = Lame::new()
+        .ok_or_else(|| EncoderError::Transient("failed to initialize LAME".to_string()))?;
     // For MP3 encoding, we set channels to 2 so that we duplicate the mono samples.
-    lame.set_channels(2).expect("Failed to set channels");
+    lame.set_channels(2).map_err(|e| lame_error("set channels", e))?;
     lame.set_sample_rate(TTSKoko::SAMPLE_RATE as u32)
-        .expect("Failed to set sample rate");
-    lame.set_quality(3).expect("Failed to set quality"); // Quality: 0 (best) to 9 (worst)
-    lame.init_params().expect("Failed to initialize parameters");
+        .map_err(|e| lame_error("set sample rate", e))?;
+    lame.set_quality(3).map_err(|e| lame_error("set quality", e))?; // Quality: 0 (best) to 9 (worst)
+    if let Some(kilobitrate) = settings.kilobitrate {
+        lame.set_kilobitrate(kilobitrate as i32)
+            .map_err(|e| lame_error("set bitrate", e))?;
+    }
+    if let Some(min_kilobitrate) = settings.vbr_min_kilobitrate {
+        set_vbr_floor(&mut lame, min_kilobitrate)?;
+    }
+    lame.init_params().map_err(|e| lame_error("initialize parameters", e))?;
 
     // Convert f32 samples to i16.
-    let pcm: Vec<i16> = raw_audio.iter().map(|&x| (x * 32767.0) as i16).collect();
+    let pcm: Vec<i16> = raw_audio.iter().map(|&x| dsp::to_i16(x)).collect();
 
     let mut mp3_data = Vec::new();
     let mut mp3_buffer = vec![0u8; pcm.len() * 2]; // Estimate a buffer size.
 
     // Encode the PCM data.
-    let encoded = lame.encode(&pcm, &pcm, &mut mp3_buffer)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let encoded = lame.encode(&pcm, &pcm, &mut mp3_buffer).map_err(|e| match e {
+        lame::EncodeError::NoMem => EncoderError::Transient("encode: out of memory".to_string()),
+        e => EncoderError::Fatal(format!("encode: {:?}", e)),
+    })?;
     mp3_data.extend_from_slice(&mp3_buffer[..encoded]);
 
     // Flush the encoder using our custom flush helper.
@@ -137,93 +875,1171 @@ fn encode_to_mp3(raw_audio: &[f32]) -> Result<Vec<u8>, StatusCode> {
     Ok(mp3_data)
 }
 
+/// Stand-in for builds without MP3 support, whose MP3 requests are rejected before encoding.
+#[cfg(not(feature = "mp3"))]
+fn encode_to_mp3(_raw_audio: &[f32], _settings: Mp3Settings) -> Result<Vec<u8>, EncoderError> {
+    Err(EncoderError::Fatal("MP3 support is not compiled in".to_string()))
+}
+
+/// Wraps raw audio samples (f32) in a Core Audio Format container as PCM.
+fn encode_to_caf(raw_audio: &[f32], codec: Codec) -> std::io::Result<Vec<u8>> {
+    let mut caf_data = Vec::new();
+    let header = caf_header(codec);
+    header.write_header(&mut caf_data, Some(raw_audio.len() as u64))?;
+    header.write_samples(&mut caf_data, raw_audio)?;
+    Ok(caf_data)
+}
+
+/// Optional metadata chunks of WAV output.
+#[derive(Clone, Copy, Default)]
+struct WavChunks<'a> {
+    /// A `PEAK` chunk, only written to float WAV.
+    peak: bool,
+    bext: Option<&'a Bext>,
+}
+
+/// Header of the mono WAV files produced by the server for a PCM codec.
+fn wav_header(codec: Codec) -> WavHeader {
+    let bits = if codec == Codec::PcmS16 { 16 } else { 32 };
+    WavHeader::new(1, TTSKoko::SAMPLE_RATE, bits)
+}
+
+/// Header of the mono CAF files produced by the server for a PCM codec.
+fn caf_header(codec: Codec) -> CafHeader {
+    match codec {
+        Codec::PcmS16 => CafHeader::new(1, TTSKoko::SAMPLE_RATE, 16, CafSampleFormat::Int),
+        _ => CafHeader::new(1, TTSKoko::SAMPLE_RATE, 32, CafSampleFormat::Float),
+    }
+}
+
+/// Error type for handlers: a status code plus a plain-text message for the client.
+type HandlerError = (StatusCode, String);
+
+fn internal_error<E: std::fmt::Display>(e: E) -> HandlerError {
+    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
 /// Health check endpoint handler.
 /// Returns a 200 OK with a simple message indicating the API is healthy.
 async fn handle_health() -> impl IntoResponse {
     (StatusCode::OK, "healthy")
 }
 
-/// The handler now returns a response that is fully compatible with the OpenAI TTS API:
-/// - When `return_audio` is true, it returns raw binary audio data with the appropriate
-///   Content-Type header so that clients can directly save or stream the file (e.g. via a curl --output command).
-/// - When false, it writes the audio to disk and returns a JSON response including the file path.
-async fn handle_tts(
-    State(tts): State<TTSKoko>,
-    Json(payload): Json<TTSRequest>,
-) -> Result<impl IntoResponse, StatusCode> {
-    let voice = payload.voice.unwrap_or_else(|| "af_sky".to_string());
-
-    // Generate raw audio samples from TTS.
-    let raw_audio = tts
-        .tts_raw_audio(&payload.input, "en-us", &voice)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    if payload.return_audio {
-        // Return raw binary audio data.
-        let (audio_data, content_type) = match payload.response_format {
-            AudioFormat::Mp3 => {
-                let data = encode_to_mp3(&raw_audio)
-                    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-                (data, "audio/mpeg")
-            }
-            AudioFormat::Wav => {
-                let mut wav_data = Vec::new();
-                let header = WavHeader::new(1, TTSKoko::SAMPLE_RATE, 32);
-                header.write_header(&mut wav_data)
-                    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-                write_audio_chunk(&mut wav_data, &raw_audio)
-                    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-                (wav_data, "audio/wav")
+/// Lists the loaded models with their characteristics, the available voices and formats.
+async fn handle_capabilities(State(state): State<AppState>) -> Json<CapabilitiesResponse> {
+    Json(CapabilitiesResponse {
+        models: state.models.describe(),
+        voices: state.models.default_model().voices(),
+        formats: supported_formats(),
+        codecs: Codec::ALL
+            .into_iter()
+            .filter(|codec| codec.is_compiled_in())
+            .map(Codec::name)
+            .collect(),
+    })
+}
+
+/// Synthesis options requested by the client.
+fn synthesis_opts(config: &ServerConfig, payload: &TTSRequest) -> TTSOpts {
+    TTSOpts {
+        comma_pause_ms: payload.comma_pause_ms,
+        link_policy: payload.link_policy,
+        elongation: payload.elongation.unwrap_or(config.elongation),
+        exceptions: config.exceptions.clone(),
+        sentence_splitter: config.sentence_splitter,
+        first_chunk_words: payload.first_chunk_words,
+        speed: payload.speed,
+        silence_fill: payload.silence_fill,
+        max_chunks: config.max_chunks,
+        aside: payload.aside,
+        mixed_language: payload.mixed_language,
+        pauses: config.pauses.clone(),
+        preview_seconds: payload.preview_seconds,
+        say_as: payload.say_as,
+        pad_short_input: payload.pad_short_input,
+        terminal_punctuation: payload.terminal_punctuation,
+        variation: payload.variation,
+        silence_retry: config.silence_retry,
+    }
+}
+
+/// Rejects input with nothing to speak, such as punctuation only. Numbers are spoken.
+fn validate_input(payload: &TTSRequest) -> Result<(), HandlerError> {
+    if !normalize::has_speakable_content(&payload.input) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Input contains no speakable text".to_string(),
+        ));
+    }
+    if let Some(voice) = &payload.voice {
+        VoiceSpec::parse(voice).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    }
+    if let Some(speed) = payload.speed {
+        if !admin::SPEED_RANGE.contains(&speed) {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "speed must be between 0.25 and 4.0".to_string(),
+            ));
+        }
+    }
+    if payload.preview_seconds.is_some_and(|seconds| !(seconds > 0.0 && seconds.is_finite())) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "preview_seconds must be a positive number of seconds".to_string(),
+        ));
+    }
+    if let Some(ceiling) = payload.limiter_ceiling_dbfs {
+        let error = if payload.clip_policy != ClipPolicy::SoftLimit {
+            Some("limiter_ceiling_dbfs needs the soft_limit clip_policy")
+        } else if !LIMITER_CEILING_RANGE.contains(&ceiling) {
+            Some("limiter_ceiling_dbfs must be between -20 and 0")
+        } else {
+            None
+        };
+        if let Some(error) = error {
+            return Err((StatusCode::BAD_REQUEST, error.to_string()));
+        }
+    }
+    if payload.max_words == Some(0) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "max_words must be a positive number of words".to_string(),
+        ));
+    }
+    if payload.variation.is_some_and(|variation| !(0.0..=1.0).contains(&variation)) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "variation must be between 0.0 and 1.0".to_string(),
+        ));
+    }
+    if let Some(tone) = payload.calibration_tone {
+        let error = if !(tone.level_dbfs.is_finite() && tone.level_dbfs <= 0.0) {
+            Some("calibration_tone level_dbfs must be at most 0".to_string())
+        } else if !(1..=MAX_CALIBRATION_DURATION_MS).contains(&tone.duration_ms) {
+            Some(format!(
+                "calibration_tone duration_ms must be between 1 and {}",
+                MAX_CALIBRATION_DURATION_MS
+            ))
+        } else {
+            None
+        };
+        if let Some(error) = error {
+            return Err((StatusCode::BAD_REQUEST, error));
+        }
+    }
+    if payload.clip_gap_ms.is_some_and(|gap| gap > MAX_CLIP_GAP_MS) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("clip_gap_ms must be at most {}", MAX_CLIP_GAP_MS),
+        ));
+    }
+    if payload.wav_peak {
+        let output = payload.output_format()?;
+        let error = if payload.stream {
+            Some("wav_peak is not available when streaming")
+        } else if output.container != Container::Wav || output.codec != Codec::PcmF32 {
+            Some("wav_peak needs float WAV output")
+        } else {
+            None
+        };
+        if let Some(error) = error {
+            return Err((StatusCode::BAD_REQUEST, error.to_string()));
+        }
+    }
+    if let Some(bext) = &payload.bext {
+        bext.validate().map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+        if payload.output_format()?.container != Container::Wav {
+            return Err((StatusCode::BAD_REQUEST, "bext needs WAV output".to_string()));
+        }
+    }
+    if let Some(preview) = payload.low_bitrate_preview {
+        let error = if payload.stream {
+            Some("low_bitrate_preview is not available when streaming".to_string())
+        } else if !MP3_KILOBITRATES.contains(&preview.bitrate) {
+            Some(format!(
+                "low_bitrate_preview bitrate must be one of {:?} kbit/s",
+                MP3_KILOBITRATES
+            ))
+        } else {
+            None
+        };
+        if let Some(error) = error {
+            return Err((StatusCode::BAD_REQUEST, error));
+        }
+    }
+    if payload.respelling && (payload.stream || payload.delivery() == Delivery::Binary) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "respelling is returned in the JSON response, which needs file or inline delivery".to_string(),
+        ));
+    }
+    if payload.first_chunk_words.is_some() && !payload.stream {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "first_chunk_words only applies when streaming".to_string(),
+        ));
+    }
+    if payload.stream && payload.delivery.is_some_and(|delivery| delivery != Delivery::Binary) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Streamed audio can only be delivered as binary".to_string(),
+        ));
+    }
+    if let Some(target) = payload.target_duration {
+        let error = if !(target > 0.0 && target.is_finite()) {
+            Some("target_duration must be a positive number of seconds")
+        } else if payload.speed.is_some() {
+            Some("target_duration cannot be combined with speed")
+        } else if payload.stream {
+            Some("target_duration is not supported when streaming")
+        } else if payload.preview_seconds.is_some() {
+            Some("target_duration cannot be combined with preview_seconds")
+        } else {
+            None
+        };
+        if let Some(error) = error {
+            return Err((StatusCode::BAD_REQUEST, error.to_string()));
+        }
+    }
+    Ok(())
+}
+
+/// Fills in the server's defaults for settings the request omits: a voice from the
+/// voice pool, if configured, and the current default speed unless the speed is
+/// fitted to a target duration. A given voice is put in its canonical form, and
+/// replaced by its fallback if it is unavailable. The input is cut to `max_words`.
+fn apply_defaults(state: &AppState, payload: &mut TTSRequest) {
+    if let Some(max_words) = payload.max_words {
+        let dropped = match normalize::truncate_words(&payload.input, max_words) {
+            Some((truncated, dropped)) => {
+                payload.input = truncated;
+                dropped
             }
+            None => 0,
         };
-        let mut response = Response::new(audio_data.into());
-        response.headers_mut().insert(
-            CONTENT_TYPE,
-            content_type.parse().expect("valid MIME type"),
-        );
-        Ok(response)
-    } else {
-        // Write audio to file and return a JSON response.
-        let timestamp = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
-
-        let output_path = match payload.response_format {
-            AudioFormat::Mp3 => {
-                let path = format!("tmp/output_{}.mp3", timestamp);
-                let data = encode_to_mp3(&raw_audio)
-                    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-                std::fs::write(&path, data)
-                    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-                path
+        payload.words_dropped = Some(dropped);
+    }
+    if let Some(spec) = payload.voice.as_deref().and_then(|voice| VoiceSpec::parse(voice).ok()) {
+        payload.voice = Some(spec.to_string());
+    }
+    if payload.voice.is_none() {
+        if let Some(pool) = &state.config.voice_pool {
+            payload.voice = Some(pool.pick(payload.user.as_deref()).to_string());
+        }
+    }
+    if let Some(fallbacks) = &state.config.voice_fallbacks {
+        let voice = request_voice(payload).to_string();
+        let tts = state.models.default_model();
+        if let Some(fallback) = fallbacks.resolve(&voice, |v| tts.has_voice(v)).filter(|v| *v != voice) {
+            eprintln!("Voice {} is unavailable, using {}", voice, fallback);
+            payload.voice = Some(fallback.to_string());
+            payload.replaced_voice = Some(voice);
+        }
+    }
+    if payload.target_duration.is_none() {
+        payload.speed.get_or_insert_with(|| state.settings.default_speed());
+    }
+}
+
+fn request_voice(payload: &TTSRequest) -> &str {
+    payload.voice.as_deref().unwrap_or("af_sky")
+}
+
+/// The fingerprint reported for a request, if fingerprints are enabled.
+fn fingerprint(
+    config: &ServerConfig,
+    model: &ModelVariant,
+    payload: &TTSRequest,
+) -> Option<Fingerprint> {
+    config.expose_fingerprint.then(|| Fingerprint {
+        model: model.kind.name(),
+        model_sha256: model.sha256.clone(),
+        voice: request_voice(payload).to_string(),
+        version: env!("CARGO_PKG_VERSION"),
+        clip_policy: payload.clip_policy,
+        comma_pause_ms: payload.comma_pause_ms,
+        speed: payload.speed,
+    })
+}
+
+/// Synthesizes the request's input with the model selected for it, and brings the
+/// samples into range per its clip policy.
+fn synthesize(
+    models: &ModelRegistry,
+    config: &ServerConfig,
+    payload: &TTSRequest,
+) -> Result<Synthesis, HandlerError> {
+    let voice = request_voice(payload);
+    let model = models.select(&payload.model, voice);
+    let (prefix, suffix) = framing(config, payload)?;
+
+    // Generate raw audio samples from TTS. Failures are isolated to this request.
+    let mut opts = synthesis_opts(config, payload);
+    let mut synthesize_at = |speed: Option<f32>| {
+        opts.speed = speed;
+        model
+            .tts
+            .tts_raw_audio_with_details(&payload.input, "en-us", voice, &opts)
+            .map_err(|e| internal_error(format!("Synthesis failed: {}", e)))
+    };
+    let ((mut raw_audio, details), duration_fit) = match payload.target_duration {
+        None => (synthesize_at(payload.speed)?, None),
+        Some(target) => {
+            // The calibration tone, clips and gaps take part of the slot; the speech is fitted to the rest.
+            let framing_duration = duration_of_samples(prefix.len() + suffix.len());
+            let speech_target = target - framing_duration;
+            if speech_target <= 0.0 {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    format!(
+                        "target_duration must be longer than the {:.2} s of calibration tone, intro and outro",
+                        framing_duration
+                    ),
+                ));
             }
-            AudioFormat::Wav => {
-                let path = format!("tmp/output_{}.wav", timestamp);
-                let spec = hound::WavSpec {
-                    channels: 1,
-                    sample_rate: TTSKoko::SAMPLE_RATE,
-                    bits_per_sample: 32,
-                    sample_format: hound::SampleFormat::Float,
-                };
-
-                let mut writer = hound::WavWriter::create(&path, spec)
-                    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-                for &sample in &raw_audio {
-                    writer.write_sample(sample)
-                        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            // Start from an estimate, then correct it once by the measured duration.
+            let mut speed = fit_speed(model.tts.estimate_duration(&payload.input), speech_target);
+            let mut synthesized = synthesize_at(Some(speed))?;
+            if !duration_matches(duration_of(&synthesized.0), speech_target) {
+                let refined = fit_speed(duration_of(&synthesized.0) * speed, speech_target);
+                if refined != speed {
+                    speed = refined;
+                    synthesized = synthesize_at(Some(speed))?;
                 }
-                writer.finalize()
-                    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-                path
             }
+            let duration = duration_of(&synthesized.0) + framing_duration;
+            let fit = DurationFit {
+                duration,
+                speed,
+                target_met: duration_matches(duration, target),
+            };
+            (synthesized, Some(fit))
+        }
+    };
+
+    if let Some(mut eq) = voice_equalizer(config, voice) {
+        eq.process(&mut raw_audio);
+    }
+    if payload.remove_dc {
+        DcBlocker::default().process(&mut raw_audio);
+    }
+    // Bring out-of-range samples into [-1, 1] the same way for every format.
+    let clip_stats = payload.clip_policy.apply(&mut raw_audio, payload.limiter_ceiling_dbfs);
+    let chunk_offsets: Vec<usize> = details.chunk_offsets.iter().map(|offset| offset + prefix.len()).collect();
+    raw_audio.splice(0..0, prefix);
+    raw_audio.extend(suffix);
+
+    let mut fingerprint = fingerprint(config, model, payload);
+    if let (Some(fingerprint), Some(fit)) = (&mut fingerprint, duration_fit) {
+        fingerprint.speed = Some(fit.speed);
+    }
+    Ok(Synthesis {
+        audio: raw_audio,
+        voice: voice.to_string(),
+        replaced_voice: payload.replaced_voice.clone(),
+        fingerprint,
+        duration_fit,
+        language_spans: language_spans_header(&details.language_spans),
+        preview_seconds: preview_header(payload),
+        clip_stats: config.report_clipping.then_some(clip_stats),
+        chunk_offsets: payload.chunk_boundaries.then_some(chunk_offsets),
+        words_dropped: payload.words_dropped,
+    })
+}
+
+/// Audio placed around the speech: before it the calibration tone and the intro clip,
+/// after it the outro clip, with the clip gap between each clip and the speech. Unlike
+/// the speech, it is not processed by the EQ, DC removal or clip policy.
+fn framing(config: &ServerConfig, payload: &TTSRequest) -> Result<(Vec<f32>, Vec<f32>), HandlerError> {
+    let clip = |name: &Option<String>| {
+        name.as_deref()
+            .map(|name| {
+                config.clips.get(name).ok_or_else(|| {
+                    let available = config.clips.names().join(", ");
+                    (StatusCode::BAD_REQUEST, format!("Unknown clip: {}; available: {}", name, available))
+                })
+            })
+            .transpose()
+    };
+    let gap_ms = payload.clip_gap_ms.unwrap_or(DEFAULT_CLIP_GAP_MS);
+    let mut silence = dsp::SilenceSource::new(payload.silence_fill);
+    let mut gap = || silence.silence(gap_ms, TTSKoko::SAMPLE_RATE);
+
+    let mut prefix = payload.calibration_tone.map(|tone| tone.samples()).unwrap_or_default();
+    if let Some(intro) = clip(&payload.intro)? {
+        prefix.extend(intro);
+        prefix.extend(gap());
+    }
+    let mut suffix = Vec::new();
+    if let Some(outro) = clip(&payload.outro)? {
+        suffix.extend(gap());
+        suffix.extend(outro);
+    }
+    Ok((prefix, suffix))
+}
+
+/// Marks a response as a preview limited to the requested number of seconds.
+fn preview_header(payload: &TTSRequest) -> Option<HeaderValue> {
+    let seconds = payload.preview_seconds?;
+    HeaderValue::from_str(&seconds.to_string()).ok()
+}
+
+/// Lists the languages detected in a `mixed_language` request with the length of each
+/// span of the synthesized text in characters, e.g. `en-us:4, ru:13, en-us:8`. `None`
+/// without spans.
+fn language_spans_header(spans: &[LanguageSpan]) -> Option<HeaderValue> {
+    if spans.is_empty() {
+        return None;
+    }
+    let spans: Vec<String> = spans
+        .iter()
+        .map(|span| format!("{}:{}", span.lan.unwrap_or("en-us"), span.chars))
+        .collect();
+    HeaderValue::from_str(&spans.join(", ")).ok()
+}
+
+/// The voice's EQ preset, if one is configured.
+fn voice_equalizer(config: &ServerConfig, voice: &str) -> Option<Equalizer> {
+    let bands = config.eq_presets.get(voice)?;
+    Some(Equalizer::new(bands, TTSKoko::SAMPLE_RATE).expect("EQ presets are validated at startup"))
+}
+
+/// Relative deviation from a target duration that still counts as meeting it.
+const DURATION_TOLERANCE: f32 = 0.05;
+
+fn duration_of(audio: &[f32]) -> f32 {
+    duration_of_samples(audio.len())
+}
+
+fn duration_of_samples(samples: usize) -> f32 {
+    samples as f32 / TTSKoko::SAMPLE_RATE as f32
+}
+
+fn duration_matches(duration: f32, target: f32) -> bool {
+    (duration - target).abs() <= target * DURATION_TOLERANCE
+}
+
+/// The speed that turns speech lasting `natural` seconds at speed 1.0 into `target`
+/// seconds, clamped to the supported range.
+fn fit_speed(natural: f32, target: f32) -> f32 {
+    (natural / target).clamp(*admin::SPEED_RANGE.start(), *admin::SPEED_RANGE.end())
+}
+
+/// Streams the audio as each chunk is synthesized. WAV and CAF are supported since
+/// their headers can be written before the total length is known. The totals are
+/// sent in trailers once synthesis has finished.
+async fn stream_tts(
+    state: AppState,
+    payload: TTSRequest,
+    output: OutputFormat,
+) -> Result<Response, HandlerError> {
+    if !output.is_streamable() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Streaming is only supported for wav and caf".to_string(),
+        ));
+    }
+    let mut header = Vec::new();
+    match output.container {
+        Container::Caf => caf_header(output.codec).write_header(&mut header, None),
+        _ => match &payload.bext {
+            Some(bext) => wav_header(output.codec).with_bext(bext.clone()).write_header(&mut header, None),
+            None => wav_header(output.codec).write_header(&mut header, None),
+        },
+    }
+    .map_err(internal_error)?;
+
+    let AppState { models, config, .. } = state;
+    let voice = request_voice(&payload);
+    let model = models.select(&payload.model, voice);
+    let mut headers = metadata_headers(
+        voice,
+        payload.replaced_voice.as_deref(),
+        fingerprint(&config, model, &payload).as_ref(),
+    );
+    // Streamed headers go out before synthesis, which splits the text the same way.
+    let spans = koko::language_spans(&payload.input, "en-us", &synthesis_opts(&config, &payload));
+    if let Some(spans) = language_spans_header(&spans) {
+        headers.insert("x-kokoros-language-spans", spans);
+    }
+    if let Some(seconds) = preview_header(&payload) {
+        headers.insert("x-kokoros-preview-seconds", seconds);
+    }
+    if let Some(dropped) = payload.words_dropped {
+        insert_truncation_headers(&mut headers, dropped);
+    }
+
+    let mut trailer_names = STREAM_TRAILERS.to_vec();
+    if config.report_clipping {
+        trailer_names.extend(CLIP_TRAILERS);
+    }
+    if payload.chunk_boundaries {
+        trailer_names.push(CHUNK_OFFSETS_HEADER);
+    }
+    headers.insert(TRAILER, trailer_names.join(", ").parse().expect("valid header value"));
+
+    let (prefix, suffix) = framing(&config, &payload)?;
+    let (tx, rx) = tokio::sync::mpsc::channel::<std::io::Result<Frame<Bytes>>>(4);
+    tx.send(Ok(Frame::data(header.into()))).await.map_err(internal_error)?;
+
+    tokio::task::spawn_blocking(move || {
+        let voice = request_voice(&payload);
+        let model = models.select(&payload.model, voice);
+        let mut eq = voice_equalizer(&config, voice);
+        let mut dc_blocker = DcBlocker::default();
+        let mut samples = 0;
+        let mut clip_stats = ClipStats::default();
+        let encode = |audio: &[f32]| {
+            let mut data = Vec::new();
+            match output.container {
+                Container::Caf => caf_header(output.codec).write_samples(&mut data, audio)?,
+                _ => wav_header(output.codec).write_samples(&mut data, audio)?,
+            }
+            std::io::Result::Ok(Bytes::from(data))
         };
+        if !prefix.is_empty() {
+            samples += prefix.len();
+            let _ = tx.blocking_send(encode(&prefix).map(Frame::data));
+        }
+        let mut chunk_offsets = Vec::new();
+        let result = model.tts.tts_raw_audio_streaming(
+            &payload.input,
+            "en-us",
+            voice,
+            &synthesis_opts(&config, &payload),
+            |mut chunk_audio| {
+                chunk_offsets.push(samples);
+                if let Some(eq) = &mut eq {
+                    eq.process(&mut chunk_audio);
+                }
+                if payload.remove_dc {
+                    dc_blocker.process(&mut chunk_audio);
+                }
+                clip_stats.merge(payload.clip_policy.apply(&mut chunk_audio, payload.limiter_ceiling_dbfs));
+                samples += chunk_audio.len();
+                let data = encode(&chunk_audio)?;
+                // A closed channel means the client went away; stop synthesizing.
+                tx.blocking_send(Ok(Frame::data(data)))
+                    .map_err(|_| "client disconnected".into())
+            },
+        );
+        match result {
+            Ok(_) => {
+                if !suffix.is_empty() {
+                    samples += suffix.len();
+                    let _ = tx.blocking_send(encode(&suffix).map(Frame::data));
+                }
+                let clip_stats = config.report_clipping.then_some(clip_stats);
+                let chunk_offsets = payload.chunk_boundaries.then_some(chunk_offsets.as_slice());
+                let trailers = stream_trailers(samples, clip_stats, chunk_offsets);
+                let _ = tx.blocking_send(Ok(Frame::trailers(trailers)));
+            }
+            Err(e) => {
+                eprintln!("Streaming synthesis failed: {}", e);
+                // Abort the response so the client does not mistake it for complete audio.
+                let _ = tx.blocking_send(Err(std::io::Error::other(e.to_string())));
+            }
+        }
+    });
 
-        let json_response = TTSResponse {
-            status: "success".to_string(),
-            file_path: Some(output_path),
-            audio: None,
+    let frames = futures_util::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|frame| (frame, rx))
+    });
+    let mut response = Response::new(Body::new(StreamBody::new(frames)));
+    response.headers_mut().extend(headers);
+    response.headers_mut().insert(
+        CONTENT_TYPE,
+        output.content_type().parse().expect("valid MIME type"),
+    );
+    Ok(response)
+}
+
+/// Encodes the audio in memory, with the given metadata chunks if it is WAV.
+fn encode_audio(
+    config: &ServerConfig,
+    output: OutputFormat,
+    wav_chunks: WavChunks,
+    raw_audio: &[f32],
+) -> Result<Vec<u8>, HandlerError> {
+    match output.container {
+        Container::Mp3 => encode_with_retries(config.encoder_retries, || {
+            let settings = Mp3Settings {
+                vbr_min_kilobitrate: config.mp3_vbr_min_bitrate,
+                ..Mp3Settings::default()
+            };
+            encode_to_mp3(raw_audio, settings)
+        })
+            .map_err(internal_error),
+        Container::Wav => {
+            let mut wav_data = Vec::new();
+            let mut header = wav_header(output.codec);
+            if wav_chunks.peak {
+                header = header.with_peak(raw_audio);
+            }
+            if let Some(bext) = wav_chunks.bext {
+                header = header.with_bext(bext.clone());
+            }
+            header.write_header(&mut wav_data, Some(raw_audio.len() as u64))
+                .map_err(internal_error)?;
+            header.write_samples(&mut wav_data, raw_audio)
+                .map_err(internal_error)?;
+            Ok(wav_data)
+        }
+        Container::Caf => encode_to_caf(raw_audio, output.codec).map_err(internal_error),
+    }
+}
+
+/// Encodes the low-bitrate preview copy of the audio.
+fn encode_preview(
+    config: &ServerConfig,
+    preview: LowBitratePreview,
+    output: OutputFormat,
+    raw_audio: &[f32],
+) -> Result<Vec<u8>, HandlerError> {
+    match output.container {
+        Container::Mp3 => encode_with_retries(config.encoder_retries, || {
+            let settings = Mp3Settings {
+                kilobitrate: Some(preview.bitrate),
+                ..Mp3Settings::default()
+            };
+            encode_to_mp3(raw_audio, settings)
+        })
+        .map_err(internal_error),
+        _ => encode_audio(config, output, WavChunks::default(), raw_audio),
+    }
+}
+
+/// Encodes the audio and writes it to `tmp/output_<name>.<ext>`, returning the path.
+fn save_audio(
+    config: &ServerConfig,
+    request: &TTSRequest,
+    output: OutputFormat,
+    raw_audio: &[f32],
+    name: &str,
+) -> Result<String, HandlerError> {
+    let path = format!("tmp/output_{}.{}", name, output.extension());
+    // hound writes exact chunk sizes, but cannot write PEAK or bext chunks.
+    let extra_chunks = request.wav_peak || request.bext.is_some();
+    if output.container == Container::Wav && !extra_chunks {
+        let float = output.codec == Codec::PcmF32;
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: TTSKoko::SAMPLE_RATE,
+            bits_per_sample: if float { 32 } else { 16 },
+            sample_format: if float {
+                hound::SampleFormat::Float
+            } else {
+                hound::SampleFormat::Int
+            },
         };
-        Ok(Json(json_response).into_response())
+
+        let mut writer = hound::WavWriter::create(&path, spec)
+            .map_err(internal_error)?;
+        for &sample in raw_audio {
+            if float {
+                writer.write_sample(sample)
+            } else {
+                writer.write_sample(dsp::to_i16(sample))
+            }
+            .map_err(internal_error)?;
+        }
+        writer.finalize()
+            .map_err(internal_error)?;
+    } else {
+        let data = encode_audio(config, output, request.wav_chunks(), raw_audio)?;
+        std::fs::write(&path, data)
+            .map_err(internal_error)?;
+    }
+    Ok(path)
+}
+
+/// The handler now returns a response that is fully compatible with the OpenAI TTS API:
+/// - When `return_audio` is true, it returns raw binary audio data with the appropriate
+///   Content-Type header so that clients can directly save or stream the file (e.g. via a curl --output command).
+/// - When false, it writes the audio to disk and returns a JSON response including the file path.
+/// - With `"delivery": "inline"`, it returns the same JSON response with the encoded audio in
+///   `audio` as base64 instead, without writing to disk.
+/// - With `low_bitrate_preview`, a small copy is encoded from the same synthesis and
+///   returned next to the audio: as a second part of a `multipart/mixed` body for binary
+///   delivery, or in `preview_file_path` or `preview_audio`.
+///
+/// The request body may be JSON or, with `Content-Type: application/msgpack`, MessagePack.
+/// Each request is recorded for `/admin/recent-requests`.
+async fn handle_tts(
+    State(state): State<AppState>,
+    RequestBody(payload): RequestBody<TTSRequest>,
+) -> Response {
+    let started = Instant::now();
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let mut record = RequestRecord {
+        timestamp,
+        voice: None,
+        format: payload
+            .output_format()
+            .ok()
+            .map(|output| format!("{}/{}", output.extension(), output.codec.name())),
+        input_chars: payload.input.chars().count(),
+        input_sha256: recent::input_hash(&payload.input),
+        stream: payload.stream,
+        audio_seconds: None,
+        elapsed_ms: 0,
+        status: 0,
+    };
+
+    let recent = state.recent.clone();
+    let response = tts_response(state, payload).await.into_response();
+    record.voice = response
+        .headers()
+        .get("x-kokoros-voice")
+        .and_then(|voice| voice.to_str().ok())
+        .map(str::to_string);
+    record.audio_seconds = response.extensions().get::<AudioSeconds>().map(|seconds| seconds.0);
+    record.elapsed_ms = started.elapsed().as_millis() as u64;
+    record.status = response.status().as_u16();
+    recent.record(record);
+    response
+}
+
+/// Length of the synthesized audio, attached to buffered responses for the request log.
+#[derive(Clone, Copy)]
+struct AudioSeconds(f32);
+
+async fn tts_response(state: AppState, mut payload: TTSRequest) -> Result<Response, HandlerError> {
+    validate_input(&payload)?;
+    let output = payload.output_format()?;
+    let preview_output = payload
+        .low_bitrate_preview
+        .as_ref()
+        .map(LowBitratePreview::output_format)
+        .transpose()?;
+    apply_defaults(&state, &mut payload);
+    if payload.stream {
+        return stream_tts(state, payload, output).await;
+    }
+
+    let AppState { models, config, .. } = state;
+    // Synthesis and encoding block (the encoder even sleeps between retries), so
+    // they run off the async runtime.
+    tokio::task::spawn_blocking(move || {
+        buffered_response(&models, &config, payload, output, preview_output)
+    })
+    .await
+    .map_err(internal_error)?
+}
+
+/// Synthesizes and encodes a non-streamed request into its response.
+fn buffered_response(
+    models: &ModelRegistry,
+    config: &ServerConfig,
+    payload: TTSRequest,
+    output: OutputFormat,
+    preview_output: Option<OutputFormat>,
+) -> Result<Response, HandlerError> {
+    let synthesis = synthesize(models, config, &payload)?;
+    let raw_audio = &synthesis.audio;
+
+    let preview = match (payload.low_bitrate_preview, preview_output) {
+        (Some(preview), Some(preview_output)) => {
+            let data = encode_preview(config, preview, preview_output, raw_audio)?;
+            Some((preview_output, data))
+        }
+        _ => None,
+    };
+
+    let mut json_response = TTSResponse {
+        status: "success".to_string(),
+        file_path: None,
+        audio: None,
+        respelling: payload.respelling.then(|| respell::respell_text(&payload.input, "en-us")),
+        preview_file_path: None,
+        preview_audio: None,
+        fingerprint: synthesis.fingerprint.clone(),
+        duration_fit: synthesis.duration_fit,
+    };
+    match payload.delivery() {
+        Delivery::Binary => {
+            // Return raw binary audio data, next to the preview if there is one.
+            let audio_data = encode_audio(config, output, payload.wav_chunks(), raw_audio)?;
+            let (content_type, body) = match preview {
+                None => (output.content_type().to_string(), audio_data),
+                Some((preview_output, preview_data)) => {
+                    let mut multipart = Multipart::new();
+                    multipart.add_part(output.content_type(), &output.file_name("speech"), &audio_data);
+                    multipart.add_part(
+                        preview_output.content_type(),
+                        &preview_output.file_name("preview"),
+                        &preview_data,
+                    );
+                    multipart.finish()
+                }
+            };
+            let mut response = Response::new(body.into());
+            response.headers_mut().extend(synthesis.headers());
+            response.headers_mut().insert(
+                CONTENT_TYPE,
+                content_type.parse().expect("valid MIME type"),
+            );
+            response.extensions_mut().insert(AudioSeconds(duration_of(raw_audio)));
+            return Ok(response);
+        }
+        Delivery::File => {
+            // Write audio to file and return a JSON response.
+            let timestamp = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs();
+
+            let output_path = save_audio(
+                config,
+                &payload,
+                output,
+                raw_audio,
+                &timestamp.to_string(),
+            )?;
+            json_response.file_path = Some(output_path);
+            if let Some((preview_output, preview_data)) = preview {
+                let path = format!("tmp/output_{}_preview.{}", timestamp, preview_output.extension());
+                std::fs::write(&path, preview_data).map_err(internal_error)?;
+                json_response.preview_file_path = Some(path);
+            }
+        }
+        Delivery::Inline => {
+            let audio_data = encode_audio(config, output, payload.wav_chunks(), raw_audio)?;
+            let base64 = &base64::engine::general_purpose::STANDARD;
+            json_response.audio = Some(base64.encode(audio_data));
+            json_response.preview_audio = preview.map(|(_, data)| base64.encode(data));
+        }
+    }
+    let mut response = (synthesis.headers(), Json(json_response)).into_response();
+    response.extensions_mut().insert(AudioSeconds(duration_of(raw_audio)));
+    Ok(response)
+}
+
+/// Synthesizes the input once and returns it encoded in every supported format as a
+/// `multipart/mixed` body, for comparing codecs on identical source audio.
+/// Only served when debugging endpoints are enabled.
+async fn handle_debug_formats(
+    State(state): State<AppState>,
+    RequestBody(mut payload): RequestBody<TTSRequest>,
+) -> Result<Response, HandlerError> {
+    validate_input(&payload)?;
+    apply_defaults(&state, &mut payload);
+    let AppState { models, config, .. } = state;
+    let (synthesis, multipart) = tokio::task::spawn_blocking(move || {
+        let synthesis = synthesize(&models, &config, &payload)?;
+        let mut multipart = Multipart::new();
+        for output in OutputFormat::ALL.into_iter().filter(|output| output.is_available()) {
+            let data = encode_audio(&config, output, payload.wav_chunks(), &synthesis.audio)?;
+            multipart.add_part(output.content_type(), &output.file_name("clip"), &data);
+        }
+        Ok::<_, HandlerError>((synthesis, multipart))
+    })
+    .await
+    .map_err(internal_error)??;
+
+    let (content_type, body) = multipart.finish();
+    let mut response = Response::new(body.into());
+    response.headers_mut().extend(synthesis.headers());
+    response.headers_mut().insert(
+        CONTENT_TYPE,
+        content_type.parse().expect("valid MIME type"),
+    );
+    Ok(response)
+}
+
+/// Queues a synthesis job and returns immediately with `202 Accepted` and the job status.
+/// The result is written to disk; its location is available from the status endpoint
+/// and, if `webhook_url` is given, POSTed to that URL once the job finishes.
+async fn handle_create_job(
+    State(state): State<AppState>,
+    RequestBody(mut job): RequestBody<JobRequest>,
+) -> Result<impl IntoResponse, HandlerError> {
+    validate_input(&job.request)?;
+    let output = job.request.output_format()?;
+    apply_defaults(&state, &mut job.request);
+    let webhook = job
+        .webhook_url
+        .as_deref()
+        .map(|url| state.config.webhooks.validate(url))
+        .transpose()
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    let status = state.jobs.create();
+    tokio::spawn(run_job(state, status.clone(), job.request, output, webhook));
+    Ok((StatusCode::ACCEPTED, Json(status)))
+}
+
+/// Runs a queued job to completion, recording the outcome and notifying the webhook.
+async fn run_job(
+    state: AppState,
+    mut status: JobStatus,
+    request: TTSRequest,
+    output: OutputFormat,
+    webhook: Option<reqwest::Url>,
+) {
+    status.status = JobState::Running;
+    state.jobs.update(status.clone());
+
+    let AppState { models, config, .. } = state.clone();
+    let name = status.id.clone();
+    let result = tokio::task::spawn_blocking(move || {
+        let synthesis = synthesize(&models, &config, &request)?;
+        save_audio(&config, &request, output, &synthesis.audio, &name)
+    })
+    .await;
+
+    match result {
+        Ok(Ok(path)) => {
+            status.status = JobState::Succeeded;
+            status.file_path = Some(path);
+        }
+        Ok(Err((_, message))) => {
+            status.status = JobState::Failed;
+            status.error = Some(message);
+        }
+        Err(e) => {
+            status.status = JobState::Failed;
+            status.error = Some(format!("Job aborted: {}", e));
+        }
+    }
+    state.jobs.update(status.clone());
+
+    if let Some(url) = webhook {
+        jobs::deliver_webhook(&state.config.webhooks, url, &status).await;
+    }
+}
+
+/// Returns the mel-spectrogram of a short input as JSON, for experimenting with other vocoders.
+#[cfg(feature = "debug-mel")]
+async fn handle_debug_mel(
+    State(state): State<AppState>,
+    RequestBody(mut payload): RequestBody<TTSRequest>,
+) -> Result<Json<crate::tts::koko::MelSpectrogram>, HandlerError> {
+    validate_input(&payload)?;
+    apply_defaults(&state, &mut payload);
+    let voice = request_voice(&payload).to_string();
+    let tts = state.models.select(&payload.model, &voice).tts.clone();
+    let mel = tokio::task::spawn_blocking(move || {
+        tts.infer_mel(&payload.input, "en-us", &voice)
+            .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))
+    })
+    .await
+    .map_err(internal_error)??;
+    Ok(Json(mel))
+}
+
+/// How `/debug/samples` encodes the samples.
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum SampleEncoding {
+    /// A JSON array of numbers.
+    #[default]
+    Json,
+    /// Base64 of the little-endian `f32` bytes, which is exact and more compact.
+    Base64,
+}
+
+#[derive(Deserialize)]
+struct SamplesQuery {
+    #[serde(default)]
+    encoding: SampleEncoding,
+}
+
+#[derive(Serialize)]
+struct SamplesResponse {
+    sample_rate: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    samples: Option<Vec<f32>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    samples_base64: Option<String>,
+}
+
+/// Returns the float samples as handed to the encoders, for use as test fixtures.
+/// Only served when debugging endpoints are enabled.
+async fn handle_debug_samples(
+    State(state): State<AppState>,
+    Query(query): Query<SamplesQuery>,
+    RequestBody(mut payload): RequestBody<TTSRequest>,
+) -> Result<Response, HandlerError> {
+    validate_input(&payload)?;
+    apply_defaults(&state, &mut payload);
+    let AppState { models, config, .. } = state;
+    let synthesis = synthesize(&models, &config, &payload)?;
+
+    let mut response = SamplesResponse {
+        sample_rate: TTSKoko::SAMPLE_RATE,
+        samples: None,
+        samples_base64: None,
+    };
+    match query.encoding {
+        SampleEncoding::Json => response.samples = Some(synthesis.audio.clone()),
+        SampleEncoding::Base64 => {
+            let bytes: Vec<u8> = synthesis.audio.iter().flat_map(|s| s.to_le_bytes()).collect();
+            response.samples_base64 = Some(base64::engine::general_purpose::STANDARD.encode(bytes));
+        }
+    }
+    Ok((synthesis.headers(), Json(response)).into_response())
+}
+
+#[derive(Deserialize, Serialize)]
+struct SpeedSetting {
+    speed: f32,
+}
+
+/// Returns the speed used for requests that omit `speed`. Requires the admin token.
+async fn handle_get_default_speed(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<SpeedSetting>, HandlerError> {
+    admin::authorize(state.config.admin_token.as_deref(), &headers)?;
+    Ok(Json(SpeedSetting {
+        speed: state.settings.default_speed(),
+    }))
+}
+
+/// Changes the speed used for requests that omit `speed`. Requires the admin token.
+async fn handle_set_default_speed(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(setting): Json<SpeedSetting>,
+) -> Result<Json<SpeedSetting>, HandlerError> {
+    admin::authorize(state.config.admin_token.as_deref(), &headers)?;
+    if !admin::SPEED_RANGE.contains(&setting.speed) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "speed must be between 0.25 and 4.0".to_string(),
+        ));
+    }
+    state.settings.set_default_speed(setting.speed);
+    Ok(Json(setting))
+}
+
+#[derive(Deserialize, Serialize)]
+struct MaintenanceSetting {
+    enabled: bool,
+}
+
+/// Returns whether maintenance mode is on. Requires the admin token.
+async fn handle_get_maintenance(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<MaintenanceSetting>, HandlerError> {
+    admin::authorize(state.config.admin_token.as_deref(), &headers)?;
+    Ok(Json(MaintenanceSetting {
+        enabled: state.settings.maintenance(),
+    }))
+}
+
+/// Turns maintenance mode on or off. Requires the admin token.
+async fn handle_set_maintenance(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(setting): Json<MaintenanceSetting>,
+) -> Result<Json<MaintenanceSetting>, HandlerError> {
+    admin::authorize(state.config.admin_token.as_deref(), &headers)?;
+    state.settings.set_maintenance(setting.enabled);
+    eprintln!(
+        "Maintenance mode {}",
+        if setting.enabled { "enabled" } else { "disabled" }
+    );
+    Ok(Json(setting))
+}
+
+/// Returns the process's memory and startup footprint, for sizing deployments.
+/// Requires the admin token.
+async fn handle_system(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<SystemInfo>, HandlerError> {
+    admin::authorize(state.config.admin_token.as_deref(), &headers)?;
+    Ok(Json(SystemInfo::gather(
+        &state.models,
+        state.config.startup_duration,
+        state.started_at.elapsed(),
+    )))
+}
+
+/// Lists the most recent speech requests, newest first. Requires the admin token.
+async fn handle_recent_requests(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<RequestRecord>>, HandlerError> {
+    admin::authorize(state.config.admin_token.as_deref(), &headers)?;
+    Ok(Json(state.recent.list()))
+}
+
+/// Returns the current status of an asynchronous job.
+async fn handle_get_job(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<JobStatus>, HandlerError> {
+    state
+        .jobs
+        .get(&id)
+        .map(Json)
+        .ok_or_else(|| (StatusCode::NOT_FOUND, format!("Unknown job: {}", id)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_with_retries() {
+        // Transient failures are retried until the budget is exhausted.
+        let mut calls = 0;
+        let result: Result<(), _> = encode_with_retries(2, || {
+            calls += 1;
+            Err(EncoderError::Transient("oom".to_string()))
+        });
+        assert!(matches!(result, Err(EncoderError::Transient(_))));
+        assert_eq!(calls, 3);
+
+        // A transient hiccup followed by success is hidden from the caller.
+        let mut calls = 0;
+        let result = encode_with_retries(2, || {
+            calls += 1;
+            if calls == 1 {
+                Err(EncoderError::Transient("oom".to_string()))
+            } else {
+                Ok(calls)
+            }
+        });
+        assert_eq!(result.unwrap(), 2);
+
+        // Fatal failures are not retried.
+        let mut calls = 0;
+        let result: Result<(), _> = encode_with_retries(2, || {
+            calls += 1;
+            Err(EncoderError::Fatal("bad params".to_string()))
+        });
+        assert!(matches!(result, Err(EncoderError::Fatal(_))));
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn test_fit_speed() {
+        assert_eq!(fit_speed(12.0, 10.0), 1.2);
+        assert_eq!(fit_speed(5.0, 10.0), 0.5);
+        // Targets out of reach are clamped to the supported speeds.
+        assert_eq!(fit_speed(100.0, 1.0), 4.0);
+        assert_eq!(fit_speed(1.0, 100.0), 0.25);
+
+        assert!(duration_matches(10.4, 10.0));
+        assert!(!duration_matches(10.6, 10.0));
+    }
+
+    #[test]
+    fn test_stream_trailers() {
+        let trailers = stream_trailers(48000, None, Some(&[0, 24000, 36000]));
+        assert_eq!(trailers["x-kokoros-duration"], "2.000");
+        assert_eq!(trailers["x-kokoros-samples"], "48000");
+        assert_eq!(trailers[CHUNK_OFFSETS_HEADER], "0, 24000, 36000");
+        assert!(!trailers.contains_key("x-kokoros-clipped"));
+
+        assert!(!stream_trailers(48000, None, None).contains_key(CHUNK_OFFSETS_HEADER));
     }
 }
@@ -0,0 +1,106 @@
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+/// Number of requests kept when the server is not configured otherwise.
+pub const DEFAULT_CAPACITY: usize = 100;
+
+/// Metadata of a handled speech request. The input is only kept as a hash, so the
+/// buffer never holds client text.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct RequestRecord {
+    /// Seconds since the Unix epoch.
+    pub timestamp: u64,
+    pub voice: Option<String>,
+    /// Container and codec, e.g. `wav/pcm_f32`; absent if the request named an invalid one.
+    pub format: Option<String>,
+    pub input_chars: usize,
+    /// Start of the input's SHA-256, for spotting repeated requests.
+    pub input_sha256: String,
+    pub stream: bool,
+    /// Length of the audio in seconds; unknown for streamed and failed requests.
+    pub audio_seconds: Option<f32>,
+    pub elapsed_ms: u64,
+    pub status: u16,
+}
+
+/// Ring buffer of the most recent requests, for the admin endpoint.
+pub struct RecentRequests {
+    capacity: usize,
+    records: Mutex<VecDeque<RequestRecord>>,
+}
+
+impl RecentRequests {
+    /// A capacity of 0 disables recording.
+    pub fn new(capacity: usize) -> Self {
+        RecentRequests {
+            capacity,
+            records: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    /// Adds a record, dropping the oldest one once the buffer is full.
+    pub fn record(&self, record: RequestRecord) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut records = self.records.lock().unwrap();
+        if records.len() == self.capacity {
+            records.pop_front();
+        }
+        records.push_back(record);
+    }
+
+    /// The recorded requests, newest first.
+    pub fn list(&self) -> Vec<RequestRecord> {
+        self.records.lock().unwrap().iter().rev().cloned().collect()
+    }
+}
+
+/// Hex digits of the SHA-256 kept per input: enough to tell inputs apart, too few to
+/// look up short inputs in a precomputed table.
+const INPUT_HASH_DIGITS: usize = 16;
+
+pub fn input_hash(input: &str) -> String {
+    Sha256::digest(input.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>()[..INPUT_HASH_DIGITS]
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(status: u16) -> RequestRecord {
+        RequestRecord {
+            timestamp: 0,
+            voice: None,
+            format: None,
+            input_chars: 5,
+            input_sha256: input_hash("hello"),
+            stream: false,
+            audio_seconds: None,
+            elapsed_ms: 0,
+            status,
+        }
+    }
+
+    #[test]
+    fn test_recent_requests_ring_buffer() {
+        let recent = RecentRequests::new(2);
+        for status in [200, 400, 500] {
+            recent.record(record(status));
+        }
+        let statuses: Vec<u16> = recent.list().iter().map(|r| r.status).collect();
+        assert_eq!(statuses, [500, 400]);
+
+        let disabled = RecentRequests::new(0);
+        disabled.record(record(200));
+        assert!(disabled.list().is_empty());
+
+        assert_eq!(input_hash("hello"), "2cf24dba5fb0a30e");
+    }
+}
@@ -0,0 +1,154 @@
+use axum::body::Body;
+use axum::extract::Request;
+use axum::Router;
+use hyper::body::Incoming;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto::Builder;
+use hyper_util::service::TowerToHyperService;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::sync::Semaphore;
+#[cfg(feature = "tls")]
+use tokio_rustls::{rustls, TlsAcceptor};
+use tower::ServiceExt;
+
+/// Default cap on simultaneously open client connections.
+pub const DEFAULT_MAX_CONNECTIONS: usize = 512;
+
+/// Sent to clients that connect while the server is at its connection cap.
+const OVER_CAPACITY_RESPONSE: &[u8] = b"HTTP/1.1 503 Service Unavailable\r\n\
+    Content-Type: text/plain\r\n\
+    Content-Length: 33\r\n\
+    Retry-After: 1\r\n\
+    Connection: close\r\n\
+    \r\n\
+    Too many connections, retry later";
+
+/// How long a client may take to complete the TLS handshake.
+#[cfg(feature = "tls")]
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Serves a router over HTTP with transport-level limits.
+///
+/// Unlike request-level limits, the connection cap also covers idle and slow
+/// clients, so they cannot exhaust the process's file descriptors.
+pub struct ServerBuilder {
+    addr: SocketAddr,
+    max_connections: usize,
+    #[cfg(feature = "tls")]
+    tls: Option<TlsAcceptor>,
+}
+
+impl ServerBuilder {
+    pub fn new(addr: SocketAddr) -> Self {
+        ServerBuilder {
+            addr,
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            #[cfg(feature = "tls")]
+            tls: None,
+        }
+    }
+
+    /// Terminates HTTPS with the PEM certificate chain and private key at the given
+    /// paths, instead of serving plain HTTP.
+    #[cfg(feature = "tls")]
+    pub fn tls(mut self, cert_path: &str, key_path: &str) -> io::Result<Self> {
+        let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidData, e);
+        let certs = rustls_pemfile::certs(&mut io::BufReader::new(std::fs::File::open(cert_path)?))
+            .collect::<Result<Vec<_>, _>>()?;
+        if certs.is_empty() {
+            return Err(invalid(format!("no certificates found in {}", cert_path)));
+        }
+        let key = rustls_pemfile::private_key(&mut io::BufReader::new(std::fs::File::open(key_path)?))?
+            .ok_or_else(|| invalid(format!("no private key found in {}", key_path)))?;
+
+        let mut config = rustls::ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .map_err(|e| invalid(e.to_string()))?;
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        self.tls = Some(TlsAcceptor::from(Arc::new(config)));
+        Ok(self)
+    }
+
+    /// Sets how many connections may be open at once. Connections past the cap are
+    /// answered with `503 Service Unavailable` and closed immediately.
+    pub fn max_connections(mut self, max_connections: usize) -> Self {
+        self.max_connections = max_connections.max(1);
+        self
+    }
+
+    /// `https` when TLS is configured, otherwise `http`.
+    pub fn scheme(&self) -> &'static str {
+        #[cfg(feature = "tls")]
+        if self.tls.is_some() {
+            return "https";
+        }
+        "http"
+    }
+
+    pub async fn serve(self, app: Router) -> io::Result<()> {
+        let listener = TcpListener::bind(self.addr).await?;
+        let slots = Arc::new(Semaphore::new(self.max_connections));
+
+        loop {
+            let (mut stream, _) = match listener.accept().await {
+                Ok(conn) => conn,
+                Err(e) => {
+                    // Typically EMFILE/ENFILE; back off instead of spinning.
+                    eprintln!("Failed to accept connection: {}", e);
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    continue;
+                }
+            };
+
+            let Ok(permit) = slots.clone().try_acquire_owned() else {
+                // A plain-text response would be garbage to a TLS client, so those are
+                // just closed.
+                #[cfg(feature = "tls")]
+                if self.tls.is_some() {
+                    continue;
+                }
+                tokio::spawn(async move {
+                    let _ = stream.write_all(OVER_CAPACITY_RESPONSE).await;
+                    let _ = stream.shutdown().await;
+                });
+                continue;
+            };
+
+            let app = app.clone();
+            #[cfg(feature = "tls")]
+            if let Some(acceptor) = self.tls.clone() {
+                tokio::spawn(async move {
+                    match tokio::time::timeout(TLS_HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                        Ok(Ok(stream)) => serve_connection(stream, app).await,
+                        Ok(Err(e)) => eprintln!("TLS handshake failed: {}", e),
+                        Err(_) => eprintln!("TLS handshake timed out"),
+                    }
+                    drop(permit);
+                });
+                continue;
+            }
+            tokio::spawn(async move {
+                serve_connection(stream, app).await;
+                // The slot is released once the connection is closed.
+                drop(permit);
+            });
+        }
+    }
+}
+
+/// Serves HTTP/1.1 or HTTP/2 on a single connection until it is closed.
+async fn serve_connection<S>(stream: S, app: Router)
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let service = app.map_request(|req: Request<Incoming>| req.map(Body::new));
+    let _ = Builder::new(TokioExecutor::new())
+        .serve_connection_with_upgrades(TokioIo::new(stream), TowerToHyperService::new(service))
+        .await;
+}
@@ -0,0 +1,77 @@
+use crate::serve::models::ModelRegistry;
+use serde::Serialize;
+use std::time::Duration;
+
+/// Memory and startup footprint of the process, as reported by `/v1/system`.
+#[derive(Serialize)]
+pub struct SystemInfo {
+    /// Resident set size of the whole process; `None` where it cannot be read.
+    pub rss_bytes: Option<u64>,
+    pub models: Vec<ModelFootprint>,
+    pub voices: VoiceFootprint,
+    /// Time from process start until the models and voices were loaded.
+    pub startup_seconds: Option<f64>,
+    pub uptime_seconds: f64,
+}
+
+#[derive(Serialize)]
+pub struct ModelFootprint {
+    pub name: &'static str,
+    /// Size of the model file, which is roughly what its weights take in memory.
+    pub size_bytes: u64,
+}
+
+/// Voices are shared between models, so they are counted once.
+#[derive(Serialize)]
+pub struct VoiceFootprint {
+    pub count: usize,
+    pub memory_bytes: usize,
+}
+
+impl SystemInfo {
+    pub fn gather(models: &ModelRegistry, startup: Option<Duration>, uptime: Duration) -> Self {
+        let tts = models.default_model();
+        SystemInfo {
+            rss_bytes: rss_bytes(),
+            models: models
+                .describe()
+                .into_iter()
+                .map(|info| ModelFootprint {
+                    name: info.name,
+                    size_bytes: info.size_bytes,
+                })
+                .collect(),
+            voices: VoiceFootprint {
+                count: tts.voices().len(),
+                memory_bytes: tts.voices_memory_bytes(),
+            },
+            startup_seconds: startup.map(|d| d.as_secs_f64()),
+            uptime_seconds: uptime.as_secs_f64(),
+        }
+    }
+}
+
+/// Reads the process's resident set size from `/proc`, which only exists on Linux.
+fn rss_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    parse_vm_rss(&status)
+}
+
+fn parse_vm_rss(status: &str) -> Option<u64> {
+    let line = status.lines().find_map(|line| line.strip_prefix("VmRSS:"))?;
+    let kb = line.trim().strip_suffix("kB")?.trim().parse::<u64>().ok()?;
+    Some(kb * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_vm_rss() {
+        let status = "Name:\tkoko\nVmPeak:\t  900000 kB\nVmRSS:\t  123456 kB\nThreads:\t8\n";
+        assert_eq!(parse_vm_rss(status), Some(123456 * 1024));
+        assert_eq!(parse_vm_rss("Name:\tkoko\n"), None);
+        assert_eq!(parse_vm_rss("VmRSS:\tlots\n"), None);
+    }
+}
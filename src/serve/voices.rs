@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// How a voice is picked from the pool.
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub enum PoolStrategy {
+    /// Cycle through the pool, one voice per request.
+    RoundRobin,
+    /// Hash the request's `user` so the same user always gets the same voice.
+    /// Requests without a `user` fall back to round-robin.
+    Hash,
+}
+
+/// Voices used for requests that do not name one.
+pub struct VoicePool {
+    voices: Vec<String>,
+    strategy: PoolStrategy,
+    next: AtomicUsize,
+}
+
+impl VoicePool {
+    /// Returns `None` for an empty pool.
+    pub fn new(voices: Vec<String>, strategy: PoolStrategy) -> Option<Self> {
+        if voices.is_empty() {
+            return None;
+        }
+        Some(VoicePool {
+            voices,
+            strategy,
+            next: AtomicUsize::new(0),
+        })
+    }
+
+    /// Picks a voice for a request, keyed by the client-provided `key` under the hash strategy.
+    pub fn pick(&self, key: Option<&str>) -> &str {
+        let index = match (self.strategy, key) {
+            (PoolStrategy::Hash, Some(key)) => fnv1a(key.as_bytes()) as usize,
+            _ => self.next.fetch_add(1, Ordering::Relaxed),
+        };
+        &self.voices[index % self.voices.len()]
+    }
+}
+
+/// Ordered substitutes per voice, for when a voice is not available, e.g. while the
+/// voice file is being replaced. Configured at startup as JSON such as
+/// `{"af_sky": ["af_bella", "af_nicole"]}`.
+#[derive(Debug, Default)]
+pub struct VoiceFallbacks {
+    chains: HashMap<String, Vec<String>>,
+}
+
+impl VoiceFallbacks {
+    pub fn from_json(value: serde_json::Value) -> Result<Self, String> {
+        let chains: HashMap<String, Vec<String>> =
+            serde_json::from_value(value).map_err(|e| format!("Invalid voice fallbacks: {}", e))?;
+        if let Some((voice, _)) = chains.iter().find(|(_, chain)| chain.is_empty()) {
+            return Err(format!("Voice fallback chain for {} is empty", voice));
+        }
+        Ok(VoiceFallbacks { chains })
+    }
+
+    /// Every voice named in the chains, for checking them against the loaded voices.
+    pub fn voices(&self) -> impl Iterator<Item = &str> {
+        self.chains
+            .iter()
+            .flat_map(|(voice, chain)| std::iter::once(voice).chain(chain))
+            .map(String::as_str)
+    }
+
+    /// The voice to synthesize with: `voice` itself if it is available, otherwise the
+    /// first available voice of its chain. `None` if neither is, or it has no chain.
+    pub fn resolve<'a>(&'a self, voice: &'a str, is_available: impl Fn(&str) -> bool) -> Option<&'a str> {
+        if is_available(voice) {
+            return Some(voice);
+        }
+        self.chains
+            .get(voice)?
+            .iter()
+            .map(String::as_str)
+            .find(|fallback| is_available(fallback))
+    }
+}
+
+/// FNV-1a, chosen because it is stable across builds and restarts, unlike `DefaultHasher`.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_voice_pool() {
+        let voices = vec!["af_sky".to_string(), "am_adam".to_string(), "bf_emma".to_string()];
+
+        let pool = VoicePool::new(voices.clone(), PoolStrategy::RoundRobin).unwrap();
+        let picked: Vec<&str> = (0..4).map(|_| pool.pick(Some("alice"))).collect();
+        assert_eq!(picked, ["af_sky", "am_adam", "bf_emma", "af_sky"]);
+
+        let pool = VoicePool::new(voices, PoolStrategy::Hash).unwrap();
+        let alice = pool.pick(Some("alice"));
+        assert!((0..10).all(|_| pool.pick(Some("alice")) == alice));
+        assert_ne!(pool.pick(None), pool.pick(None));
+
+        assert!(VoicePool::new(Vec::new(), PoolStrategy::Hash).is_none());
+    }
+
+    #[test]
+    fn test_voice_fallbacks() {
+        let fallbacks = VoiceFallbacks::from_json(serde_json::json!({
+            "af_sky": ["af_bella", "af_nicole"],
+        }))
+        .unwrap();
+
+        // af_sky and af_bella are temporarily unavailable, e.g. during a reload.
+        let mut unavailable = vec!["af_sky", "af_bella"];
+        let available = |unavailable: &[&str], voice: &str| !unavailable.contains(&voice);
+        assert_eq!(fallbacks.resolve("af_sky", |v| available(&unavailable, v)), Some("af_nicole"));
+        assert_eq!(fallbacks.resolve("am_adam", |v| available(&unavailable, v)), Some("am_adam"));
+
+        // Once af_sky is back, it is used again.
+        unavailable.remove(0);
+        assert_eq!(fallbacks.resolve("af_sky", |v| available(&unavailable, v)), Some("af_sky"));
+
+        // Nothing in the chain is available, or the voice has no chain.
+        assert_eq!(fallbacks.resolve("af_sky", |_| false), None);
+        assert_eq!(fallbacks.resolve("bf_emma", |_| false), None);
+
+        assert!(VoiceFallbacks::from_json(serde_json::json!({"af_sky": []})).is_err());
+        assert!(VoiceFallbacks::from_json(serde_json::json!(["af_sky"])).is_err());
+    }
+}
@@ -1,20 +1,176 @@
 use crate::tts::tokenize::tokenize;
 use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
 use std::path::Path;
-use std::sync::Arc;
+use std::sync::{Arc, PoisonError, RwLock};
 
 use crate::onn::ort_koko::{self};
+use crate::tts::{normalize, ssml};
+use crate::tts::voice::VoiceSpec;
 use crate::utils;
+use crate::utils::dsp;
 use crate::utils::fileio::load_json_file;
 
 use espeak_rs::text_to_phonemes;
 
+/// Per-call synthesis options beyond text, language and voice.
+#[derive(Clone, Debug, Default)]
+pub struct TTSOpts {
+    /// Extra silence inserted at commas, in milliseconds, to make enumerations
+    /// easier to follow. `None` leaves pacing to the model.
+    pub comma_pause_ms: Option<u32>,
+    /// How URLs and email addresses in the text are read out.
+    pub link_policy: normalize::LinkPolicy,
+    /// How elongated words and repeated punctuation are read.
+    pub elongation: normalize::ElongationPolicy,
+    /// Read the content of SSML `<say-as>` elements as characters, ordinals or telephone
+    /// numbers, as their `interpret-as` says. Without it, such markup is read as text.
+    pub say_as: bool,
+    /// Patterns that the normalization above leaves verbatim or spells as configured.
+    pub exceptions: Arc<normalize::Exceptions>,
+    /// How the text is split into sentences before chunking.
+    pub sentence_splitter: normalize::SentenceSplitter,
+    /// Synthesize this many leading words as a separate first chunk, so that
+    /// streaming clients get audio as early as possible. `None` keeps the regular chunking.
+    pub first_chunk_words: Option<usize>,
+    /// What the inserted pauses are filled with.
+    pub silence_fill: dsp::SilenceFill,
+    /// Upper bound on the number of chunks, to bound per-chunk overhead on fragmented
+    /// input. Adjacent chunks are merged to stay under it. `None` means no limit.
+    pub max_chunks: Option<usize>,
+    /// Speaking rate relative to the natural rate; `None` means 1.0.
+    pub speed: Option<f32>,
+    /// Read text in parentheses as a spoken aside, quieter and optionally faster.
+    /// `None` reads it like the surrounding text.
+    pub aside: Option<Aside>,
+    /// Detect spans in non-Latin scripts and phonemize each in its own language.
+    pub mixed_language: bool,
+    /// Insert pauses after punctuation, with durations per mark and language.
+    /// `comma_pause_ms` takes precedence for commas. `None` leaves pacing to the model.
+    pub pauses: Option<Arc<normalize::PauseMap>>,
+    /// Stop once this many seconds of audio are produced, cutting the output to that length.
+    /// `None` synthesizes the whole text.
+    pub preview_seconds: Option<f32>,
+    /// Synthesize inputs of a word or two after a carrier phrase that is then cut off,
+    /// since the model renders them poorly without context. English only.
+    pub pad_short_input: bool,
+    /// End input that lacks terminal punctuation with the language's full stop, for a
+    /// natural ending, and trim the pause it adds after the last word.
+    pub terminal_punctuation: bool,
+    /// Randomly vary prosody and pace by up to this much, from 0.0 to 1.0, so that
+    /// repeated text does not sound identical. `None` keeps the output deterministic.
+    pub variation: Option<f32>,
+    /// Re-synthesize chunks that come out (near) silent. `None` accepts any output.
+    pub silence_retry: Option<SilenceRetry>,
+}
+
+/// When a chunk's audio counts as silent, and how often it is synthesized again.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SilenceRetry {
+    /// Output with an RMS level below this is treated as a failed synthesis.
+    pub min_rms: f32,
+    pub retries: u32,
+}
+
+/// Synthesis kept producing silent audio for non-empty text.
+#[derive(Debug)]
+pub struct SilentOutputError;
+
+impl std::fmt::Display for SilentOutputError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "synthesis produced silent audio")
+    }
+}
+
+impl std::error::Error for SilentOutputError {}
+
+/// How parenthetical asides are rendered.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize)]
+pub struct Aside {
+    /// Linear gain applied to the aside's audio.
+    #[serde(default = "Aside::default_gain")]
+    pub gain: f32,
+    /// Speaking rate of the aside, relative to the surrounding text.
+    #[serde(default = "Aside::default_speed")]
+    pub speed: f32,
+}
+
+impl Aside {
+    fn default_gain() -> f32 {
+        0.6
+    }
+
+    fn default_speed() -> f32 {
+        1.0
+    }
+}
+
+/// A piece of text that is synthesized in one inference.
+#[derive(Clone, Debug, PartialEq)]
+struct Chunk {
+    text: String,
+    /// Whether the text is a parenthetical aside.
+    aside: bool,
+    /// Language of the text when it was detected to differ from the request's.
+    lan: Option<&'static str>,
+}
+
+impl Chunk {
+    /// Whether the two chunks are synthesized the same way, so they can be merged.
+    fn renders_like(&self, other: &Chunk) -> bool {
+        self.aside == other.aside && self.lan == other.lan
+    }
+}
+
+/// Intermediate mel-spectrogram of one utterance.
+#[cfg(feature = "debug-mel")]
+#[derive(Debug, serde::Serialize)]
+pub struct MelSpectrogram {
+    /// Number of mel bins per frame.
+    pub n_mels: usize,
+    pub frames: usize,
+    /// Frames per second of audio.
+    pub frame_rate: f32,
+    /// Row-major `[n_mels, frames]` values, as produced by the model.
+    pub data: Vec<f32>,
+}
+
+/// Token budget of a chunk; the model accepts 512, leaving 12 tokens of margin.
+const MAX_CHUNK_TOKENS: usize = 500;
+
+/// Average number of model tokens spoken per second at speed 1.0.
+const TOKENS_PER_SECOND: f32 = 14.0;
+
+/// Inputs with at most this many words are padded when `TTSOpts::pad_short_input` is set.
+const SHORT_INPUT_MAX_WORDS: usize = 2;
+/// Neutral phrase spoken before short inputs; the sentence break leaves a gap to cut at.
+const SHORT_INPUT_CARRIER: &str = "Here it is.";
+/// Shortest silence between the carrier and the input that is taken for the break.
+const CARRIER_GAP_MS: u32 = 60;
+/// RMS level below which a frame is silent when looking for the break.
+const CARRIER_GAP_THRESHOLD: f32 = 0.01;
+/// Audio kept before the input's onset, followed by a fade-in over the same length,
+/// so the cut neither clips the attack nor clicks.
+const CARRIER_CUT_MARGIN_MS: usize = 10;
+
+/// Silence kept after the last word when `TTSOpts::terminal_punctuation` added a full stop.
+const TERMINAL_TAIL_MS: u32 = 150;
+/// RMS level below which a frame counts as silent when trimming that tail.
+const TERMINAL_TAIL_THRESHOLD: f32 = 0.01;
+
+/// Largest relative change of the speed at full variation.
+const MAX_SPEED_VARIATION: f32 = 0.04;
+/// Largest change of each prosody component at full variation, relative to the style's RMS.
+const MAX_STYLE_VARIATION: f32 = 0.05;
+/// Style components from this index on condition the prosody predictor; the ones
+/// before it condition the decoder, which sets the voice's timbre.
+const PROSODY_OFFSET: usize = 128;
+
 #[derive(Clone)]
 pub struct TTSKoko {
-    #[allow(dead_code)]
     model_path: String,
-    model: Arc<ort_koko::OrtKoko>,
-    styles: HashMap<String, Vec<[[f32; 256]; 1]>>,
+    model: Arc<RwLock<ort_koko::OrtKoko>>,
+    styles: Arc<HashMap<String, Vec<[[f32; 256]; 1]>>>,
 }
 
 impl TTSKoko {
@@ -34,10 +190,10 @@ impl TTSKoko {
             eprintln!("load model from: {}", model_path);
         }
 
-        let model = Arc::new(
+        let model = Arc::new(RwLock::new(
             ort_koko::OrtKoko::new(model_path.to_string())
                 .expect("Failed to create Kokoro TTS model"),
-        );
+        ));
 
         // TODO: if(not streaming) { model.print_info(); }
         // model.print_info();
@@ -45,26 +201,73 @@ impl TTSKoko {
         let mut instance = TTSKoko {
             model_path: model_path.to_string(),
             model,
-            styles: HashMap::new(),
+            styles: Arc::new(HashMap::new()),
         };
         instance.load_voices();
         instance
     }
 
-    fn split_text_into_chunks(&self, text: &str, max_tokens: usize) -> Vec<String> {
+    /// Creates an instance running a different model file that shares this
+    /// instance's voices, e.g. a quantized variant of the same model.
+    pub fn with_model(&self, model_path: &str) -> Result<Self, String> {
+        let model = ort_koko::OrtKoko::new(model_path.to_string())?;
+        eprintln!("load model from: {}", model_path);
+        Ok(TTSKoko {
+            model_path: model_path.to_string(),
+            model: Arc::new(RwLock::new(model)),
+            styles: self.styles.clone(),
+        })
+    }
+
+    pub fn model_path(&self) -> &str {
+        &self.model_path
+    }
+
+    /// Names of all loaded voices, sorted.
+    pub fn voices(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.styles.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    pub fn has_voice(&self, name: &str) -> bool {
+        self.styles.contains_key(name)
+    }
+
+    /// Memory taken by the loaded voice style vectors.
+    pub fn voices_memory_bytes(&self) -> usize {
+        self.styles
+            .values()
+            .map(|style| std::mem::size_of_val(style.as_slice()))
+            .sum()
+    }
+
+    /// Splits text into chunks of at most `max_tokens` tokens at sentence boundaries.
+    /// Sentences keep their own terminators. A sentence without one gets a period, except
+    /// at the end of text that stops mid-sentence, which `ends_sentence` is false for.
+    fn split_text_into_chunks(
+        &self,
+        text: &str,
+        max_tokens: usize,
+        splitter: normalize::SentenceSplitter,
+        ends_sentence: bool,
+    ) -> Vec<String> {
         let mut chunks = Vec::new();
 
         // First split by sentences - using common sentence ending punctuation
-        let sentences: Vec<&str> = text
-            .split(|c| c == '.' || c == '?' || c == '!' || c == ';')
-            .filter(|s| !s.trim().is_empty())
-            .collect();
+        let sentences = splitter.split(text);
+        let sentence_count = sentences.len();
 
         let mut current_chunk = String::new();
 
-        for sentence in sentences {
-            // Clean up the sentence and add back punctuation
-            let sentence = format!("{}.", sentence.trim());
+        for (i, sentence) in sentences.into_iter().enumerate() {
+            // Clean up the sentence and end it with a period if it lacks its own terminator
+            let sentence = sentence.trim();
+            let closed = ends_sentence || i + 1 < sentence_count;
+            let sentence = match closed && !normalize::ends_with_terminator(sentence) {
+                true => format!("{}.", sentence),
+                false => sentence.to_string(),
+            };
 
             // Convert to phonemes to check token count
             let sentence_phonemes = text_to_phonemes(&sentence, "en", None, true, false)
@@ -136,38 +339,305 @@ impl TTSKoko {
         lan: &str,
         style_name: &str,
     ) -> Result<Vec<f32>, Box<dyn std::error::Error>> {
-        // Split text into appropriate chunks
-        let chunks = self.split_text_into_chunks(txt, 500); // Using 500 to leave 12 tokens of margin
+        self.tts_raw_audio_with_opts(txt, lan, style_name, &TTSOpts::default())
+    }
+
+    pub fn tts_raw_audio_with_opts(
+        &self,
+        txt: &str,
+        lan: &str,
+        style_name: &str,
+        opts: &TTSOpts,
+    ) -> Result<Vec<f32>, Box<dyn std::error::Error>> {
+        let (audio, _) = self.tts_raw_audio_with_details(txt, lan, style_name, opts)?;
+        Ok(audio)
+    }
+
+    /// Like [`Self::tts_raw_audio_with_opts`], but also returns the sample offset at
+    /// which each synthesis chunk starts, for clients that process chunks separately,
+    /// and the language spans of the processed text.
+    pub fn tts_raw_audio_with_details(
+        &self,
+        txt: &str,
+        lan: &str,
+        style_name: &str,
+        opts: &TTSOpts,
+    ) -> Result<(Vec<f32>, SynthesisDetails), Box<dyn std::error::Error>> {
         let mut final_audio = Vec::new();
+        let mut chunk_offsets = Vec::new();
+        let language_spans = self.tts_raw_audio_streaming(txt, lan, style_name, opts, |chunk_audio| {
+            chunk_offsets.push(final_audio.len());
+            final_audio.extend(chunk_audio);
+            Ok(())
+        })?;
+        Ok((final_audio, SynthesisDetails { chunk_offsets, language_spans }))
+    }
+
+    /// Synthesizes the text chunk by chunk, handing each chunk's audio to `on_chunk`
+    /// as soon as it is ready. An error from `on_chunk` stops synthesis. Returns the
+    /// language spans that were synthesized, as [`language_spans`] does.
+    pub fn tts_raw_audio_streaming<F>(
+        &self,
+        txt: &str,
+        lan: &str,
+        style_name: &str,
+        opts: &TTSOpts,
+        mut on_chunk: F,
+    ) -> Result<Vec<LanguageSpan>, Box<dyn std::error::Error>>
+    where
+        F: FnMut(Vec<f32>) -> Result<(), Box<dyn std::error::Error>>,
+    {
+        let (txt, added_full_stop) = prepare_text(txt, lan, opts);
+        let pad_short_input = opts.pad_short_input
+            && lan.starts_with("en")
+            && txt.split_whitespace().count() <= SHORT_INPUT_MAX_WORDS;
+
+        let segments = split_segments(txt, opts);
+        let spans = match opts.mixed_language {
+            true => spans_of(&segments),
+            false => Vec::new(),
+        };
+        let mut chunks: Vec<Chunk> = segments
+            .into_iter()
+            .flat_map(|(segment, ends_sentence)| {
+                self.split_text_into_chunks(&segment.text, MAX_CHUNK_TOKENS, opts.sentence_splitter, ends_sentence)
+                    .into_iter()
+                    .map(move |text| Chunk { text, ..segment.clone() })
+            })
+            .collect();
+        if let Some(max_chunks) = opts.max_chunks.filter(|&max| chunks.len() > max) {
+            let sized = chunks
+                .into_iter()
+                .map(|chunk| {
+                    let tokens = self.count_tokens(&chunk.text);
+                    (chunk, tokens)
+                })
+                .collect();
+            chunks = merge_chunks(sized, max_chunks, MAX_CHUNK_TOKENS);
+            if chunks.len() > max_chunks {
+                return Err(format!(
+                    "input needs {} chunks, more than the maximum of {}",
+                    chunks.len(),
+                    max_chunks
+                )
+                .into());
+            }
+        }
+        if let Some(words) = opts.first_chunk_words {
+            chunks = split_first_chunk(chunks, words);
+        }
 
         // Get style vectors once
         let styles = self.mix_styles(style_name)?;
+        let mut variation = opts.variation.filter(|&amount| amount > 0.0).map(Variation::new);
+        let mut silence = dsp::SilenceSource::new(opts.silence_fill);
+        let mut remaining = opts
+            .preview_seconds
+            .map(|seconds| (seconds * TTSKoko::SAMPLE_RATE as f32) as usize);
 
-        for chunk in chunks {
-            // Convert chunk to phonemes
-            let phonemes = text_to_phonemes(&chunk, lan, None, true, false)
-                .map_err(|e| Box::new(e) as Box<dyn std::error::Error>)?
-                .join("");
+        let chunk_count = chunks.len();
+        for (i, chunk) in chunks.into_iter().enumerate() {
+            let lan = chunk.lan.unwrap_or(lan);
+            let aside = opts.aside.filter(|_| chunk.aside);
+            let speed = opts.speed.unwrap_or(1.0) * aside.map_or(1.0, |a| a.speed);
+            let (styles, speed) = match &mut variation {
+                Some(variation) => variation.apply(&styles, speed),
+                None => (styles.clone(), speed),
+            };
 
-            let tokens = vec![tokenize(&phonemes)];
+            let synthesize = |text: &str| {
+                retry_on_silence(opts.silence_retry, || match pad_short_input {
+                    true => self.synthesize_with_carrier(text, lan, &styles, speed),
+                    false => self.synthesize_chunk(text, lan, &styles, speed),
+                })
+            };
 
-            match self.model.infer(tokens, styles.clone()) {
-                Ok(chunk_audio) => {
-                    let chunk_audio: Vec<f32> = chunk_audio.iter().cloned().collect();
-                    final_audio.extend_from_slice(&chunk_audio);
-                }
-                Err(e) => {
-                    eprintln!("Error processing chunk: {:?}", e);
-                    eprintln!("Chunk text was: {:?}", chunk);
-                    return Err(Box::new(std::io::Error::new(
-                        std::io::ErrorKind::Other,
-                        format!("Chunk processing failed: {:?}", e),
-                    )));
+            // Synthesize phrases that are followed by a pause on their own and put the pauses between them.
+            let mut chunk_audio = Vec::new();
+            let mut pause_ms = 0;
+            for (phrase, phrase_pause_ms) in pause_phrases(&chunk.text, lan, opts) {
+                if pause_ms > 0 {
+                    chunk_audio.extend(silence.silence(pause_ms, TTSKoko::SAMPLE_RATE));
                 }
+                chunk_audio.extend(synthesize(&phrase)?);
+                pause_ms = phrase_pause_ms;
+            }
+            if let Some(aside) = aside {
+                chunk_audio.iter_mut().for_each(|sample| *sample *= aside.gain);
+            }
+            if added_full_stop && i + 1 == chunk_count {
+                dsp::trim_trailing_silence(
+                    &mut chunk_audio,
+                    TTSKoko::SAMPLE_RATE,
+                    TERMINAL_TAIL_MS,
+                    TERMINAL_TAIL_THRESHOLD,
+                );
+            }
+            if pause_ms > 0 && i + 1 < chunk_count {
+                chunk_audio.extend(silence.silence(pause_ms, TTSKoko::SAMPLE_RATE));
+            }
+            if let Some(remaining) = &mut remaining {
+                chunk_audio.truncate(*remaining);
+                *remaining -= chunk_audio.len();
+            }
+            on_chunk(chunk_audio)?;
+            if remaining == Some(0) {
+                // The preview is complete; skip synthesizing the rest.
+                break;
+            }
+        }
+
+        Ok(spans)
+    }
+
+    /// Phonemizes and synthesizes one chunk of text that fits in the model's context.
+    fn synthesize_chunk(
+        &self,
+        chunk: &str,
+        lan: &str,
+        styles: &[Vec<f32>],
+        speed: f32,
+    ) -> Result<Vec<f32>, Box<dyn std::error::Error>> {
+        let tokens = vec![self.chunk_tokens(chunk, lan)?];
+
+        match self.infer(tokens, styles.to_vec(), speed) {
+            Ok(chunk_audio) => Ok(chunk_audio),
+            Err(e) => {
+                eprintln!("Error processing chunk: {:?}", e);
+                eprintln!("Chunk text was: {:?}", chunk);
+                Err(Box::new(std::io::Error::new(
+                    std::io::ErrorKind::Other,
+                    format!("Chunk processing failed: {}", e),
+                )))
             }
         }
+    }
+
+    /// Synthesizes a short text after [`SHORT_INPUT_CARRIER`] and cuts the carrier off
+    /// at the break between them. Falls back to the text alone if no break is found.
+    fn synthesize_with_carrier(
+        &self,
+        chunk: &str,
+        lan: &str,
+        styles: &[Vec<f32>],
+        speed: f32,
+    ) -> Result<Vec<f32>, Box<dyn std::error::Error>> {
+        let padded = format!("{} {}", SHORT_INPUT_CARRIER, chunk);
+        let audio = self.synthesize_chunk(&padded, lan, styles, speed)?;
+        let sample_rate = TTSKoko::SAMPLE_RATE;
+        let Some(onset) = dsp::last_onset_after_gap(&audio, sample_rate, CARRIER_GAP_MS, CARRIER_GAP_THRESHOLD)
+        else {
+            return self.synthesize_chunk(chunk, lan, styles, speed);
+        };
+        let margin = CARRIER_CUT_MARGIN_MS * sample_rate as usize / 1000;
+        let mut audio = audio[onset.saturating_sub(margin)..].to_vec();
+        for (i, sample) in audio.iter_mut().take(margin).enumerate() {
+            *sample *= i as f32 / margin as f32;
+        }
+        Ok(audio)
+    }
+
+    /// Rough duration of the text in seconds at speed 1.0, estimated from its phoneme count.
+    pub fn estimate_duration(&self, text: &str) -> f32 {
+        self.count_tokens(text) as f32 / TOKENS_PER_SECOND
+    }
+
+    /// Number of model tokens in a piece of text, as counted by the chunker.
+    fn count_tokens(&self, text: &str) -> usize {
+        let phonemes = text_to_phonemes(text, "en", None, true, false)
+            .unwrap_or_default()
+            .join("");
+        tokenize(&phonemes).len()
+    }
+
+    /// Converts a chunk of text to phonemes and then to model tokens.
+    fn chunk_tokens(&self, chunk: &str, lan: &str) -> Result<Vec<i64>, Box<dyn std::error::Error>> {
+        let phonemes = text_to_phonemes(chunk, lan, None, true, false)
+            .map_err(|e| Box::new(e) as Box<dyn std::error::Error>)?
+            .join("");
+        Ok(tokenize(&phonemes))
+    }
+
+    /// Runs a single inference, isolating failures to the calling request.
+    fn infer(
+        &self,
+        tokens: Vec<Vec<i64>>,
+        styles: Vec<Vec<f32>>,
+        speed: f32,
+    ) -> Result<Vec<f32>, Box<dyn std::error::Error>> {
+        let audio = self.run_isolated(tokens, styles, |model, tokens, styles| {
+            model.infer(tokens, styles, speed)
+        })?;
+        Ok(audio.iter().cloned().collect())
+    }
+
+    /// Synthesizes text that fits in a single chunk and returns the intermediate
+    /// mel-spectrogram instead of the waveform, for use with other vocoders.
+    ///
+    /// Only models exported with a `mel` output support this; see [`ort_koko::OrtKoko::infer_mel`].
+    #[cfg(feature = "debug-mel")]
+    pub fn infer_mel(
+        &self,
+        txt: &str,
+        lan: &str,
+        style_name: &str,
+    ) -> Result<MelSpectrogram, Box<dyn std::error::Error>> {
+        let styles = self.mix_styles(style_name)?;
+        let tokens = self.chunk_tokens(txt, lan)?;
+        if tokens.len() > 510 {
+            return Err("text is too long for a single mel-spectrogram, split it first".into());
+        }
 
-        Ok(final_audio)
+        let (audio, mel) = self.run_isolated(vec![tokens], styles, |model, tokens, styles| {
+            model.infer_mel(tokens, styles)
+        })?;
+        let &[_, n_mels, frames] = mel.shape() else {
+            return Err(format!("unexpected mel shape {:?}", mel.shape()).into());
+        };
+        // The hop size is not part of the model's metadata; derive it from the waveform.
+        let duration = audio.len() as f32 / TTSKoko::SAMPLE_RATE as f32;
+        Ok(MelSpectrogram {
+            n_mels,
+            frames,
+            frame_rate: if duration > 0.0 { frames as f32 / duration } else { 0.0 },
+            data: mel.iter().cloned().collect(),
+        })
+    }
+
+    /// Runs `run` against the session, isolating failures to the calling request.
+    ///
+    /// Malformed inputs are rejected up front. If ONNX Runtime errors or panics on
+    /// otherwise valid inputs, the session is rebuilt from the model file so that
+    /// it cannot poison subsequent requests, and the error is returned to the caller.
+    fn run_isolated<T, F>(
+        &self,
+        tokens: Vec<Vec<i64>>,
+        styles: Vec<Vec<f32>>,
+        run: F,
+    ) -> Result<T, Box<dyn std::error::Error>>
+    where
+        F: FnOnce(&ort_koko::OrtKoko, Vec<Vec<i64>>, Vec<Vec<f32>>) -> Result<T, Box<dyn std::error::Error>>,
+    {
+        ort_koko::OrtKoko::validate_inputs(&tokens, &styles)
+            .map_err(|e| format!("Invalid model input: {}", e))?;
+
+        let result = {
+            let model = self.model.read().unwrap_or_else(PoisonError::into_inner);
+            panic::catch_unwind(AssertUnwindSafe(|| run(&model, tokens, styles)))
+        };
+
+        let err = match result {
+            Ok(Ok(output)) => return Ok(output),
+            Ok(Err(e)) => format!("Inference failed: {}", e),
+            Err(_) => "Inference panicked".to_string(),
+        };
+
+        eprintln!("{}, recreating ONNX session", err);
+        let mut model = self.model.write().unwrap_or_else(PoisonError::into_inner);
+        if let Err(e) = model.reload() {
+            eprintln!("Failed to recreate ONNX session: {}", e);
+        }
+        Err(err.into())
     }
 
     pub fn tts(
@@ -201,42 +671,26 @@ impl TTSKoko {
         &self,
         style_name: &str,
     ) -> Result<Vec<Vec<f32>>, Box<dyn std::error::Error>> {
-        if !style_name.contains("+") {
-            if let Some(style) = self.styles.get(style_name) {
-                let styles = vec![style[0][0].to_vec()];
-                Ok(styles)
-            } else {
-                Err(format!("can not found from styles_map: {}", style_name).into())
-            }
-        } else {
-            eprintln!("parsing style mix");
-            let styles: Vec<&str> = style_name.split('+').collect();
-
-            let mut style_names = Vec::new();
-            let mut style_portions = Vec::new();
-
-            for style in styles {
-                if let Some((name, portion)) = style.split_once('.') {
-                    if let Ok(portion) = portion.parse::<f32>() {
-                        style_names.push(name);
-                        style_portions.push(portion * 0.1);
-                    }
-                }
-            }
-            eprintln!("styles: {:?}, portions: {:?}", style_names, style_portions);
-
-            let mut blended_style = vec![vec![0.0; 256]; 1];
-
-            for (name, portion) in style_names.iter().zip(style_portions.iter()) {
-                if let Some(style) = self.styles.get(*name) {
-                    let style_slice = &style[0][0]; // This is a [256] array
-                                                    // Blend into the blended_style
-                    for j in 0..256 {
-                        blended_style[0][j] += style_slice[j] * portion;
+        let style = |name: &str| {
+            self.styles
+                .get(name)
+                .map(|style| &style[0][0]) // This is a [256] array
+                .ok_or_else(|| format!("can not found from styles_map: {}", name))
+        };
+        match VoiceSpec::parse(style_name)? {
+            VoiceSpec::Single(name) => Ok(vec![style(&name)?.to_vec()]),
+            VoiceSpec::Blend(parts) => {
+                eprintln!("parsing style mix: {:?}", parts);
+                let mut blended_style = vec![vec![0.0; 256]; 1];
+                for (name, weight) in &parts {
+                    let portion = weight * 0.1;
+                    // Blend into the blended_style
+                    for (blended, value) in blended_style[0].iter_mut().zip(style(name)?) {
+                        *blended += value * portion;
                     }
                 }
+                Ok(blended_style)
             }
-            Ok(blended_style)
         }
     }
 
@@ -244,6 +698,7 @@ impl TTSKoko {
         // load from json, get styles
         let values = load_json_file(TTSKoko::JSON_DATA_F);
         if let Ok(values) = values {
+            let mut styles = HashMap::new();
             if let Some(obj) = values.as_object() {
                 for (key, value) in obj {
                     // Check if value is an array
@@ -269,10 +724,11 @@ impl TTSKoko {
                         }
 
                         // Insert multidimensional array into HashMap
-                        self.styles.insert(key.clone(), tensor);
+                        styles.insert(key.clone(), tensor);
                     }
                 }
             }
+            self.styles = Arc::new(styles);
 
             eprintln!("voice styles loaded: {}", self.styles.len());
             let mut keys: Vec<_> = self.styles.keys().cloned().collect();
@@ -286,3 +742,487 @@ impl TTSKoko {
         }
     }
 }
+
+/// Small random changes to the prosody half of the style and to the speed, for
+/// `TTSOpts::variation`. The timbre half is left alone so the voice stays recognizable.
+struct Variation {
+    amount: f32,
+    state: u32,
+}
+
+impl Variation {
+    fn new(amount: f32) -> Self {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.subsec_nanos());
+        Variation::with_seed(amount, nanos)
+    }
+
+    fn with_seed(amount: f32, seed: u32) -> Self {
+        Variation {
+            amount: amount.clamp(0.0, 1.0),
+            // xorshift never leaves a zero state.
+            state: seed | 1,
+        }
+    }
+
+    /// Uniform in `[-1, 1]`.
+    fn uniform(&mut self) -> f32 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        self.state as f32 / u32::MAX as f32 * 2.0 - 1.0
+    }
+
+    fn apply(&mut self, styles: &[Vec<f32>], speed: f32) -> (Vec<Vec<f32>>, f32) {
+        let styles = styles
+            .iter()
+            .map(|style| {
+                let scale = self.amount * MAX_STYLE_VARIATION * dsp::rms(style);
+                let mut style = style.clone();
+                for value in style.iter_mut().skip(PROSODY_OFFSET) {
+                    *value += self.uniform() * scale;
+                }
+                style
+            })
+            .collect();
+        let speed = speed * (1.0 + self.uniform() * self.amount * MAX_SPEED_VARIATION);
+        (styles, speed)
+    }
+}
+
+/// Runs `synthesize` until its output is louder than the policy's threshold, up to
+/// `retries` extra times, and fails with [`SilentOutputError`] if it never is.
+fn retry_on_silence<F>(
+    policy: Option<SilenceRetry>,
+    mut synthesize: F,
+) -> Result<Vec<f32>, Box<dyn std::error::Error>>
+where
+    F: FnMut() -> Result<Vec<f32>, Box<dyn std::error::Error>>,
+{
+    let Some(policy) = policy else {
+        return synthesize();
+    };
+    for attempt in 0..=policy.retries {
+        let audio = synthesize()?;
+        if dsp::rms(&audio) >= policy.min_rms {
+            return Ok(audio);
+        }
+        eprintln!("Synthesis attempt {} produced silent audio", attempt + 1);
+    }
+    Err(Box::new(SilentOutputError))
+}
+
+/// Merges adjacent chunks, given with their token counts, until there are at most
+/// `max_chunks`. The pair with the fewest combined tokens is merged first. Merged
+/// chunks stay within `max_tokens`, and asides or other-language spans are not merged
+/// with regular text, so more chunks than the cap may remain if the text is too long for it.
+fn merge_chunks(mut chunks: Vec<(Chunk, usize)>, max_chunks: usize, max_tokens: usize) -> Vec<Chunk> {
+    while chunks.len() > max_chunks.max(1) {
+        let smallest_pair = (0..chunks.len() - 1)
+            .filter(|&i| chunks[i].0.renders_like(&chunks[i + 1].0))
+            .map(|i| (i, chunks[i].1 + chunks[i + 1].1))
+            .filter(|&(_, tokens)| tokens <= max_tokens)
+            .min_by_key(|&(_, tokens)| tokens);
+        let Some((i, tokens)) = smallest_pair else {
+            break;
+        };
+        let (next, _) = chunks.remove(i + 1);
+        let merged = &mut chunks[i];
+        merged.0.text = format!("{} {}", merged.0.text, next.text);
+        merged.1 = tokens;
+    }
+    chunks.into_iter().map(|(chunk, _)| chunk).collect()
+}
+
+/// Sample offsets and language spans of a synthesis, beyond its audio.
+#[derive(Clone, Debug, Default)]
+pub struct SynthesisDetails {
+    /// Sample offset at which each chunk starts.
+    pub chunk_offsets: Vec<usize>,
+    /// Empty unless `TTSOpts::mixed_language` is set.
+    pub language_spans: Vec<LanguageSpan>,
+}
+
+/// A run of the synthesized text in one language.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LanguageSpan {
+    /// The detected language, or `None` for the request's.
+    pub lan: Option<&'static str>,
+    /// Length in characters.
+    pub chars: usize,
+}
+
+/// The language spans of the text as it is synthesized, after `say-as` expansion,
+/// exceptions, link rewriting and terminal punctuation. Empty unless
+/// `opts.mixed_language` is set.
+pub fn language_spans(txt: &str, lan: &str, opts: &TTSOpts) -> Vec<LanguageSpan> {
+    if !opts.mixed_language {
+        return Vec::new();
+    }
+    let (txt, _) = prepare_text(txt, lan, opts);
+    spans_of(&split_segments(txt, opts))
+}
+
+/// Adjacent segments in the same language joined into spans.
+fn spans_of(segments: &[(Chunk, bool)]) -> Vec<LanguageSpan> {
+    let mut spans: Vec<LanguageSpan> = Vec::new();
+    for (segment, _) in segments {
+        let chars = segment.text.chars().count();
+        match spans.last_mut() {
+            Some(span) if span.lan == segment.lan => span.chars += chars,
+            _ => spans.push(LanguageSpan { lan: segment.lan, chars }),
+        }
+    }
+    spans
+}
+
+/// Applies the text rewrites that come before chunking, and returns the text with
+/// whether a full stop was added for `terminal_punctuation`.
+fn prepare_text(txt: &str, lan: &str, opts: &TTSOpts) -> (String, bool) {
+    let txt = match opts.say_as {
+        true => ssml::expand_say_as(txt, lan),
+        false => txt.to_string(),
+    };
+    let txt = opts.exceptions.apply(&txt, |text| normalize_fragment(text, opts));
+    let terminated = match opts.terminal_punctuation {
+        true => normalize::add_terminal_punctuation(&txt, lan),
+        false => None,
+    };
+    match terminated {
+        Some(terminated) => (terminated, true),
+        None => (txt, false),
+    }
+}
+
+/// Rewrites links and elongations in text between exceptions, as the options say.
+fn normalize_fragment(text: &str, opts: &TTSOpts) -> String {
+    let text = normalize::normalize_links(text, opts.link_policy);
+    match opts.elongation {
+        normalize::ElongationPolicy::Collapse => normalize::collapse_elongation(&text),
+        normalize::ElongationPolicy::Literal => text,
+    }
+}
+
+/// Splits text into the segments that are chunked separately: asides, if they are
+/// rendered differently, and spans in other languages, which are phonemized with their
+/// own g2p. Each comes with whether it ends a sentence, which is only true for the last
+/// one: the others are followed by more of their sentence, or end in their own terminator.
+fn split_segments(txt: String, opts: &TTSOpts) -> Vec<(Chunk, bool)> {
+    let segments = match opts.aside {
+        Some(_) => normalize::split_parentheticals(&txt),
+        None => vec![(txt, false)],
+    };
+    let mut split = Vec::new();
+    for (segment, aside) in segments {
+        let spans = match opts.mixed_language {
+            true => normalize::split_language_spans(&segment),
+            false => vec![(segment, None)],
+        };
+        split.extend(spans.into_iter().map(|(text, lan)| (Chunk { text, aside, lan }, false)));
+    }
+    if let Some((_, ends_sentence)) = split.last_mut() {
+        *ends_sentence = true;
+    }
+    split
+}
+
+/// Splits a chunk into the phrases that are synthesized on their own, each with the
+/// pause after it in milliseconds. With a pause map, phrases end at every mark that
+/// has a pause; with only `comma_pause_ms`, at commas. The pause after the chunk's
+/// last phrase is for its closing punctuation and is inserted before the next chunk.
+fn pause_phrases(text: &str, lan: &str, opts: &TTSOpts) -> Vec<(String, u32)> {
+    match (&opts.pauses, opts.comma_pause_ms) {
+        (Some(pauses), comma_pause_ms) => normalize::split_at_pauses(text, lan)
+            .into_iter()
+            .map(|(phrase, mark)| {
+                let pause_ms = mark.map_or(0, |mark| match (mark, comma_pause_ms) {
+                    (normalize::PauseMark::Comma, Some(ms)) => ms,
+                    _ => pauses.pause_ms(lan, mark),
+                });
+                (phrase, pause_ms)
+            })
+            .collect(),
+        (None, Some(pause_ms)) => {
+            let parts = normalize::split_at_commas(text, lan);
+            let last = parts.len().saturating_sub(1);
+            parts
+                .into_iter()
+                .enumerate()
+                .map(|(i, part)| (part, if i < last { pause_ms } else { 0 }))
+                .collect()
+        }
+        (None, None) => vec![(text.to_string(), 0)],
+    }
+}
+
+/// Moves the first `words` words of the first chunk into a chunk of their own.
+/// Short prefixes synthesize quickly, which cuts the time to first audio.
+fn split_first_chunk(mut chunks: Vec<Chunk>, words: usize) -> Vec<Chunk> {
+    let Some(first) = chunks.first() else {
+        return chunks;
+    };
+    let first_words: Vec<&str> = first.text.split_whitespace().collect();
+    if words == 0 || first_words.len() <= words {
+        return chunks;
+    }
+    let prefix = Chunk {
+        text: first_words[..words].join(" "),
+        ..first.clone()
+    };
+    let rest = Chunk {
+        text: first_words[words..].join(" "),
+        ..first.clone()
+    };
+    chunks.splice(0..1, [prefix, rest]);
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODEL_PATH: &str = "checkpoints/kokoro-v0_19.onnx";
+
+    #[tokio::test]
+    async fn test_bad_input_does_not_poison_session() {
+        // Needs the real model and voices; skip when they have not been downloaded.
+        if !Path::new(MODEL_PATH).exists() || !Path::new(TTSKoko::JSON_DATA_F).exists() {
+            eprintln!("skipping: model or voices not available");
+            return;
+        }
+        let tts = TTSKoko::new(MODEL_PATH).await;
+        let styles = tts.mix_styles("af_sky").unwrap();
+
+        // Rejected before reaching the session.
+        let empty = tts.infer(vec![vec![]], styles.clone(), 1.0);
+        assert!(empty.is_err());
+
+        // Token id far outside the vocabulary makes ONNX Runtime fail mid-run.
+        let out_of_vocab = tts.infer(vec![vec![0, 10_000, 0]], styles.clone(), 1.0);
+        assert!(out_of_vocab.is_err());
+
+        let good = tts.infer(vec![tokenize("həlˈoʊ")], styles, 1.0);
+        assert!(!good.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_pad_short_input() {
+        if !Path::new(MODEL_PATH).exists() || !Path::new(TTSKoko::JSON_DATA_F).exists() {
+            eprintln!("skipping: model or voices not available");
+            return;
+        }
+        let tts = TTSKoko::new(MODEL_PATH).await;
+        let plain = tts.tts_raw_audio_with_opts("Done", "en-us", "af_sky", &TTSOpts::default()).unwrap();
+        let opts = TTSOpts {
+            pad_short_input: true,
+            ..TTSOpts::default()
+        };
+        let padded = tts.tts_raw_audio_with_opts("Done", "en-us", "af_sky", &opts).unwrap();
+        let carrier = tts
+            .tts_raw_audio_with_opts(SHORT_INPUT_CARRIER, "en-us", "af_sky", &TTSOpts::default())
+            .unwrap();
+
+        // The carrier is cut off, the word is kept at a similar level, and the cut does not click.
+        assert!(padded.len() < carrier.len() + plain.len());
+        assert!(dsp::rms(&padded) > 0.5 * dsp::rms(&plain), "{} vs {}", dsp::rms(&padded), dsp::rms(&plain));
+        assert!(dsp::peak(&padded[..24]) < 0.05);
+    }
+
+    fn chunks(texts: &[&str]) -> Vec<Chunk> {
+        texts
+            .iter()
+            .map(|text| Chunk {
+                text: text.to_string(),
+                aside: false,
+                lan: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_split_first_chunk() {
+        let original = chunks(&["one two three four.", "five."]);
+        assert_eq!(
+            split_first_chunk(original.clone(), 2),
+            chunks(&["one two", "three four.", "five."])
+        );
+        // A first chunk that is already short enough is left alone.
+        assert_eq!(split_first_chunk(original.clone(), 4), original);
+        assert!(split_first_chunk(Vec::new(), 2).is_empty());
+    }
+
+    #[test]
+    fn test_merge_chunks_caps_fragmented_input() {
+        let fragments: Vec<(Chunk, usize)> = (0..1000)
+            .map(|i| (chunks(&[&format!("w{}.", i)]).remove(0), 3))
+            .collect();
+        let merged = merge_chunks(fragments, 8, 500);
+        assert_eq!(merged.len(), 8);
+        assert!(merged[0].text.starts_with("w0. w1."));
+        let words: usize = merged.iter().map(|c| c.text.split_whitespace().count()).sum();
+        assert_eq!(words, 1000);
+
+        // Chunks are not merged past the token budget, even if the cap is missed.
+        let long = chunks(&["a", "b", "c"]).into_iter().zip([300, 300, 100]).collect();
+        assert_eq!(merge_chunks(long, 1, 500), chunks(&["a", "b c"]));
+
+        // Asides stay separate from the surrounding text.
+        let mut mixed = chunks(&["a", "b", "c"]);
+        mixed[1].aside = true;
+        let mixed = mixed.into_iter().map(|c| (c, 1)).collect();
+        assert_eq!(merge_chunks(mixed, 1, 500).len(), 3);
+
+        // So do spans in another language.
+        let mut mixed = chunks(&["a", "b", "c"]);
+        mixed[2].lan = Some("ru");
+        let mixed = mixed.into_iter().map(|c| (c, 1)).collect();
+        assert_eq!(merge_chunks(mixed, 1, 500).len(), 2);
+    }
+
+    #[test]
+    fn test_exceptions_keep_their_spacing() {
+        let exceptions = normalize::Exceptions::from_json(serde_json::json!([{"pattern": r"\bv\d+(\.\d+)+\b"}]));
+        let opts = TTSOpts {
+            exceptions: Arc::new(exceptions.unwrap()),
+            elongation: normalize::ElongationPolicy::Collapse,
+            ..TTSOpts::default()
+        };
+        let normalize = |text| opts.exceptions.apply(text, |text| normalize_fragment(text, &opts));
+        assert_eq!(normalize("Update to v1.2.3 now"), "Update to v1.2.3 now");
+        assert_eq!(normalize("v1.2 and v1.3 sooooon, see www.example.com"), "v1.2 and v1.3 soon, see example dot com");
+        assert_eq!(normalize("  v1.2  "), "  v1.2  ");
+    }
+
+    #[test]
+    fn test_split_segments() {
+        let segments = |text: &str, opts: &TTSOpts| -> Vec<(String, Option<&str>, bool)> {
+            split_segments(text.to_string(), opts)
+                .into_iter()
+                .map(|(chunk, ends_sentence)| (chunk.text.trim().to_string(), chunk.lan, ends_sentence))
+                .collect()
+        };
+        let opts = TTSOpts {
+            mixed_language: true,
+            ..TTSOpts::default()
+        };
+        // Only the last span ends the sentence, and each keeps its own marks.
+        assert_eq!(
+            segments("She said привет, мир! to us", &opts),
+            [
+                ("She said".to_string(), None, false),
+                ("привет, мир!".to_string(), Some("ru"), false),
+                ("to us".to_string(), None, true),
+            ]
+        );
+        // Asides are read as part of their sentence.
+        let opts = TTSOpts {
+            aside: Some(Aside { gain: 0.6, speed: 1.0 }),
+            ..TTSOpts::default()
+        };
+        assert_eq!(
+            segments("We left (briefly) and came back", &opts),
+            [
+                ("We left".to_string(), None, false),
+                ("briefly".to_string(), None, false),
+                ("and came back".to_string(), None, true),
+            ]
+        );
+        assert_eq!(
+            segments("She said привет, мир!", &TTSOpts::default()),
+            [("She said привет, мир!".to_string(), None, true)]
+        );
+    }
+
+    #[test]
+    fn test_language_spans() {
+        let opts = TTSOpts {
+            mixed_language: true,
+            say_as: true,
+            ..TTSOpts::default()
+        };
+        // Spans describe the text after say-as expansion, not the markup.
+        let spans = language_spans(r#"Code <say-as interpret-as="characters">AB</say-as>: привет"#, "en-us", &opts);
+        assert_eq!(
+            spans,
+            [LanguageSpan { lan: None, chars: "Code ay bee: ".len() }, LanguageSpan { lan: Some("ru"), chars: 6 }]
+        );
+        assert!(language_spans("привет", "en-us", &TTSOpts::default()).is_empty());
+    }
+
+    #[test]
+    fn test_pause_phrases() {
+        let phrases = |opts: &TTSOpts| pause_phrases("At 10:30, eggs, milk, bread. Wait...", "en-us", opts);
+        let strings = |phrases: &[(&str, u32)]| -> Vec<(String, u32)> {
+            phrases.iter().map(|&(phrase, ms)| (phrase.to_string(), ms)).collect()
+        };
+
+        // Phrases keep their own marks, so commas are not read as sentence ends.
+        let opts = TTSOpts {
+            pauses: Some(Arc::new(normalize::PauseMap::default())),
+            comma_pause_ms: Some(90),
+            ..TTSOpts::default()
+        };
+        assert_eq!(
+            phrases(&opts),
+            strings(&[("At 10:30,", 90), ("eggs,", 90), ("milk,", 90), ("bread.", 400), ("Wait...", 500)])
+        );
+
+        let opts = TTSOpts {
+            comma_pause_ms: Some(90),
+            ..TTSOpts::default()
+        };
+        assert_eq!(
+            phrases(&opts),
+            strings(&[("At 10:30,", 90), ("eggs,", 90), ("milk,", 90), ("bread. Wait...", 0)])
+        );
+
+        assert_eq!(
+            phrases(&TTSOpts::default()),
+            strings(&[("At 10:30, eggs, milk, bread. Wait...", 0)])
+        );
+    }
+
+    #[test]
+    fn test_variation_is_bounded() {
+        let style: Vec<f32> = (0..256).map(|i| (i as f32 * 0.1).sin()).collect();
+        let bound = 0.5 * MAX_STYLE_VARIATION * dsp::rms(&style);
+        let mut variation = Variation::with_seed(0.5, 42);
+
+        let (first, first_speed) = variation.apply(std::slice::from_ref(&style), 1.0);
+        let (second, _) = variation.apply(std::slice::from_ref(&style), 1.0);
+        assert_ne!(first, second);
+        assert_eq!(first[0][..PROSODY_OFFSET], style[..PROSODY_OFFSET]);
+        assert!(first[0].iter().zip(&style).all(|(a, b)| (a - b).abs() <= bound));
+        assert!((first_speed - 1.0).abs() <= 0.5 * MAX_SPEED_VARIATION);
+    }
+
+    #[test]
+    fn test_retry_on_silence() {
+        let policy = Some(SilenceRetry {
+            min_rms: 0.01,
+            retries: 1,
+        });
+
+        // A silent first result is retried and the good retry is returned.
+        let mut attempts = 0;
+        let audio = retry_on_silence(policy, || {
+            attempts += 1;
+            Ok(if attempts == 1 { vec![0.0; 100] } else { vec![0.5; 100] })
+        });
+        assert_eq!(audio.unwrap(), vec![0.5; 100]);
+        assert_eq!(attempts, 2);
+
+        // Output that stays silent, including empty output, is an error.
+        let mut attempts = 0;
+        let result = retry_on_silence(policy, || {
+            attempts += 1;
+            Ok(Vec::new())
+        });
+        assert!(result.unwrap_err().is::<SilentOutputError>());
+        assert_eq!(attempts, 2);
+
+        // Without a policy, silent output is accepted as is.
+        assert!(retry_on_silence(None, || Ok(vec![0.0; 10])).is_ok());
+    }
+}
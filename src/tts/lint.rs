@@ -0,0 +1,64 @@
+use espeak_rs::text_to_phonemes;
+
+use crate::tts::normalize::Exceptions;
+use crate::tts::vocab::VOCAB;
+
+/// Phoneme symbols used by a configuration entry that the model's symbol table lacks.
+/// The tokenizer drops such symbols, so they would silently go unspoken.
+#[derive(Debug, PartialEq)]
+pub struct LintIssue {
+    /// The entry the symbols come from, e.g. `exception "\bk8s\b"`.
+    pub source: String,
+    /// Empty when the entry could not be phonemized at all.
+    pub symbols: Vec<char>,
+}
+
+impl std::fmt::Display for LintIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.symbols.is_empty() {
+            return write!(f, "{}: cannot be phonemized", self.source);
+        }
+        let symbols: Vec<String> = self.symbols.iter().map(|c| format!("{:?}", c)).collect();
+        write!(f, "{}: unknown phoneme symbols {}", self.source, symbols.join(", "))
+    }
+}
+
+/// Symbols in `phonemes` that are not in the model's vocabulary, each listed once.
+pub fn unknown_symbols(phonemes: &str) -> Vec<char> {
+    let mut unknown = Vec::new();
+    for c in phonemes.chars() {
+        if !VOCAB.contains_key(&c) && !unknown.contains(&c) {
+            unknown.push(c);
+        }
+    }
+    unknown
+}
+
+/// Phonemizes every spelling in the exception list and reports the ones that produce
+/// symbols the model cannot represent, or that cannot be phonemized at all.
+pub fn lint_exceptions(exceptions: &Exceptions, lan: &str) -> Vec<LintIssue> {
+    exceptions
+        .spellings()
+        .filter_map(|(pattern, spell)| {
+            let source = format!("exception {:?} (spelled {:?})", pattern, spell);
+            match text_to_phonemes(spell, lan, None, true, false) {
+                Ok(phonemes) => {
+                    let symbols = unknown_symbols(&phonemes.join(""));
+                    (!symbols.is_empty()).then_some(LintIssue { source, symbols })
+                }
+                Err(_) => Some(LintIssue { source, symbols: Vec::new() }),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unknown_symbols() {
+        assert!(unknown_symbols("həlˈoʊ wˈɜːld.").is_empty());
+        assert_eq!(unknown_symbols("ħɛ̃lɫo ℓℓ"), vec!['\u{303}', 'ℓ']);
+    }
+}
@@ -1,5 +1,9 @@
 pub mod koko;
+pub mod lint;
 pub mod normalize;
 pub mod phonemizer;
+pub mod respell;
+pub mod ssml;
 pub mod tokenize;
 pub mod vocab;
+pub mod voice;
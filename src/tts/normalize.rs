@@ -1,5 +1,7 @@
 use lazy_static::lazy_static;
 use regex::Regex;
+use serde::Deserialize;
+use std::collections::HashMap;
 
 lazy_static! {
     static ref WHITESPACE_RE: Regex = Regex::new(r"[^\S \n]").unwrap();
@@ -25,6 +27,232 @@ lazy_static! {
     static ref X_POSSESSIVE_RE: Regex = Regex::new(r"(?<=X')S\b").unwrap();
     static ref INITIALS_RE: Regex = Regex::new(r"(?:[A-Za-z]\.){2,} [a-z]").unwrap();
     static ref ACRONYM_RE: Regex = Regex::new(r"(?i)(?<=[A-Z])\.(?=[A-Z])").unwrap();
+    static ref EMAIL_RE: Regex =
+        Regex::new(r"\b[A-Za-z0-9._%+-]+@[A-Za-z0-9-]+(?:\.[A-Za-z0-9-]+)*\.[A-Za-z]{2,}\b").unwrap();
+    static ref URL_RE: Regex = Regex::new(r#"\b(?:https?://|www\.)[^\s<>"]+"#).unwrap();
+    static ref SPACE_BEFORE_PUNCT_RE: Regex = Regex::new(r" +([.,;:!?])").unwrap();
+}
+
+/// How URLs and email addresses in the input are read out.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkPolicy {
+    /// Read the address in words, e.g. "example dot com slash docs".
+    #[default]
+    Spoken,
+    /// Replace the address with "link" or "email address".
+    Summarize,
+    /// Drop the address from the text.
+    Omit,
+}
+
+/// How runs of a repeated character, as in "soooo good!!!", are read.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum ElongationPolicy {
+    /// Shorten the runs to a normal spelling, e.g. "so good!".
+    Collapse,
+    /// Leave the text as written.
+    #[default]
+    Literal,
+}
+
+/// Letters of roman numerals, whose runs are meaningful, as in "viii".
+const ROMAN_NUMERALS: &str = "ivxlcdm";
+
+/// Shortens runs of three or more identical lowercase letters or punctuation marks.
+///
+/// Letter runs become a single letter, except "oo" and "ee" before a consonant
+/// ("goooood" is read "good"). Periods are left alone, since "..." is meaningful.
+/// Only lowercase ASCII letters in words with other letters are collapsed, so
+/// all-caps words ("AAA", "III"), roman numerals ("viii"), single-letter words
+/// ("www", "zzz") and other scripts keep their runs.
+pub fn collapse_elongation(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::with_capacity(text.len());
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let run = chars[i..].iter().take_while(|&&n| n == c).count();
+        let keep = if run < 3 || c == '.' {
+            run
+        } else if c.is_ascii_punctuation() {
+            1
+        } else if c.is_ascii_lowercase() && is_elongated_word(&chars, i) {
+            let next = chars.get(i + run).map(|n| n.to_ascii_lowercase());
+            let before_consonant =
+                next.is_some_and(|n| n.is_ascii_alphabetic() && !"aeiouy".contains(n));
+            if matches!(c, 'o' | 'e') && before_consonant {
+                2
+            } else {
+                1
+            }
+        } else {
+            run
+        };
+        out.extend(std::iter::repeat_n(c, keep));
+        i += run;
+    }
+    out
+}
+
+/// Whether the word around the run starting at `chars[start]` reads as an elongated
+/// spelling: it has other letters and is not a roman numeral.
+fn is_elongated_word(chars: &[char], start: usize) -> bool {
+    let begin = chars[..start]
+        .iter()
+        .rposition(|c| !c.is_alphanumeric())
+        .map_or(0, |i| i + 1);
+    let end = chars[start..]
+        .iter()
+        .position(|c| !c.is_alphanumeric())
+        .map_or(chars.len(), |i| start + i);
+    let word = &chars[begin..end];
+    let has_other_letters = word.iter().any(|&n| n != chars[start] && n.is_alphabetic());
+    let roman = word.iter().all(|n| ROMAN_NUMERALS.contains(n.to_ascii_lowercase()));
+    has_other_letters && !roman
+}
+
+/// Rewrites URLs and email addresses according to the policy, so that they are
+/// not read out character by character.
+pub fn normalize_links(text: &str, policy: LinkPolicy) -> String {
+    let text = EMAIL_RE.replace_all(text, |caps: &regex::Captures| match policy {
+        LinkPolicy::Spoken => speak_address(&caps[0]),
+        LinkPolicy::Summarize => "email address".to_string(),
+        LinkPolicy::Omit => String::new(),
+    });
+    let text = URL_RE.replace_all(&text, |caps: &regex::Captures| {
+        // Sentence punctuation directly after a URL is not part of it.
+        let url = caps[0].trim_end_matches(['.', ',', ';', ':', '!', '?', ')', '\'']);
+        let trailing = &caps[0][url.len()..];
+        let replacement = match policy {
+            LinkPolicy::Spoken => speak_url(url),
+            LinkPolicy::Summarize => "link".to_string(),
+            LinkPolicy::Omit => String::new(),
+        };
+        format!("{}{}", replacement, trailing)
+    });
+    let mut text = MULTI_SPACE_RE.replace_all(&text, " ").into_owned();
+    if policy == LinkPolicy::Omit {
+        // Close the gaps left where an address stood before punctuation.
+        text = SPACE_BEFORE_PUNCT_RE.replace_all(&text, "$1").into_owned();
+    }
+    text.trim().to_string()
+}
+
+/// Reads a URL without its scheme, "www." prefix, query string or fragment.
+fn speak_url(url: &str) -> String {
+    let url = url
+        .trim_start_matches("https://")
+        .trim_start_matches("http://")
+        .trim_start_matches("www.");
+    let url = url.split(['?', '#']).next().unwrap_or(url);
+    speak_address(url.trim_end_matches('/'))
+}
+
+/// Spells out the separators of an address as words.
+fn speak_address(address: &str) -> String {
+    let mut spoken = String::new();
+    for c in address.chars() {
+        let word = match c {
+            '.' => " dot ",
+            '/' => " slash ",
+            '@' => " at ",
+            '-' => " dash ",
+            '_' => " underscore ",
+            ':' => " colon ",
+            _ => {
+                spoken.push(c);
+                continue;
+            }
+        };
+        spoken.push_str(word);
+    }
+    spoken.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Patterns that normalization must leave verbatim, or read a specific way, such as
+/// version strings and technical tokens. Loaded once at startup from a JSON list:
+///
+/// ```json
+/// [{"pattern": "\\bv\\d+(\\.\\d+)+\\b"}, {"pattern": "\\bk8s\\b", "spell": "kubernetes"}]
+/// ```
+///
+/// A spelling may refer to capture groups of its pattern as `$1`.
+#[derive(Clone, Debug, Default)]
+pub struct Exceptions {
+    entries: Vec<(Regex, Option<String>)>,
+}
+
+#[derive(Deserialize)]
+struct ExceptionEntry {
+    pattern: String,
+    #[serde(default)]
+    spell: Option<String>,
+}
+
+impl Exceptions {
+    pub fn from_json(value: serde_json::Value) -> Result<Self, String> {
+        let entries: Vec<ExceptionEntry> =
+            serde_json::from_value(value).map_err(|e| format!("invalid exception list: {}", e))?;
+        let entries = entries
+            .into_iter()
+            .map(|entry| {
+                let re = Regex::new(&entry.pattern)
+                    .map_err(|e| format!("invalid exception pattern {:?}: {}", entry.pattern, e))?;
+                if re.is_match("") {
+                    return Err(format!("exception pattern matches empty text: {:?}", entry.pattern));
+                }
+                Ok((re, entry.spell))
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Exceptions { entries })
+    }
+
+    /// Patterns and spellings of the entries that are not left verbatim.
+    pub fn spellings(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries
+            .iter()
+            .filter_map(|(re, spell)| Some((re.as_str(), spell.as_deref()?)))
+    }
+
+    /// Runs `normalize` on the text between exceptions, and keeps each exception
+    /// verbatim or replaces it with its spelling. Where patterns overlap, the match
+    /// that starts first wins, then the one listed first.
+    pub fn apply(&self, text: &str, normalize: impl Fn(&str) -> String) -> String {
+        let mut out = String::with_capacity(text.len());
+        let mut pos = 0;
+        loop {
+            let next = self
+                .entries
+                .iter()
+                .filter_map(|(re, spell)| re.captures_at(text, pos).map(|caps| (caps, spell)))
+                .filter(|(caps, _)| !caps[0].is_empty())
+                .min_by_key(|(caps, _)| caps.get(0).unwrap().start());
+            let Some((caps, spell)) = next else {
+                push_normalized(&mut out, &text[pos..], &normalize);
+                return out;
+            };
+            let found = caps.get(0).unwrap();
+            push_normalized(&mut out, &text[pos..found.start()], &normalize);
+            match spell {
+                Some(spell) => caps.expand(spell, &mut out),
+                None => out.push_str(found.as_str()),
+            }
+            pos = found.end();
+        }
+    }
+}
+
+/// Appends the normalized fragment with its own leading and trailing whitespace, which
+/// `normalize` may trim, so that it stays apart from the exceptions around it.
+fn push_normalized(out: &mut String, fragment: &str, normalize: impl Fn(&str) -> String) {
+    let start = fragment.len() - fragment.trim_start().len();
+    let end = fragment.trim_end().len().max(start);
+    out.push_str(&fragment[..start]);
+    out.push_str(normalize(fragment).trim());
+    out.push_str(&fragment[end..]);
 }
 
 pub fn normalize_text(text: &str) -> String {
@@ -69,3 +297,677 @@ pub fn normalize_text(text: &str) -> String {
 
     text.trim().to_string()
 }
+
+/// Whether the text has anything to speak: punctuation and symbols alone do not count,
+/// while digits do, since numbers are read out.
+pub fn has_speakable_content(text: &str) -> bool {
+    text.chars().any(char::is_alphanumeric)
+}
+
+/// Splits text into sentences after `.`, `?`, `!` and `;`, keeping a run of terminators
+/// with its sentence. A period between two digits is a decimal point, as in "3.14", and
+/// does not end a sentence. Sentences made only of terminators are dropped.
+pub fn split_sentences(text: &str) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut sentences = Vec::new();
+    let mut current = String::new();
+
+    for (i, &c) in chars.iter().enumerate() {
+        current.push(c);
+        let decimal_point = c == '.'
+            && i > 0
+            && chars[i - 1].is_ascii_digit()
+            && chars.get(i + 1).is_some_and(|n| n.is_ascii_digit());
+        if is_terminator(c) && !decimal_point && !chars.get(i + 1).is_some_and(|&n| is_terminator(n)) {
+            sentences.push(std::mem::take(&mut current));
+        }
+    }
+    sentences.push(current);
+
+    sentences.retain(|s| !is_blank_sentence(s));
+    sentences
+}
+
+/// Whether a split-off sentence has nothing but whitespace and terminators.
+fn is_blank_sentence(sentence: &str) -> bool {
+    sentence.trim().trim_matches(is_terminator).trim().is_empty()
+}
+
+/// Whether a sentence, ignoring closing quotes and brackets, ends with its own
+/// terminator, as opposed to being cut off mid-sentence.
+pub fn ends_with_terminator(sentence: &str) -> bool {
+    sentence
+        .trim_end()
+        .trim_end_matches(is_closer)
+        .ends_with(|c| TERMINAL_MARKS.contains(&c) || is_terminator(c))
+}
+
+/// Which sentence splitter chunking uses.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum SentenceSplitter {
+    /// Split at every terminator except decimal points; fast but splits "Dr." and "e.g.".
+    #[default]
+    Simple,
+    /// Also keep abbreviations, initials, quotes and parentheses together.
+    Robust,
+}
+
+impl SentenceSplitter {
+    pub fn split(self, text: &str) -> Vec<String> {
+        match self {
+            SentenceSplitter::Simple => split_sentences(text),
+            SentenceSplitter::Robust => split_sentences_robust(text),
+        }
+    }
+}
+
+/// Title and reference abbreviations that are usually followed by a capitalized word or a number.
+const ABBREVIATIONS: &[&str] = &[
+    "mr", "mrs", "ms", "dr", "prof", "sr", "jr", "st", "mt", "vs", "fig", "gen", "gov", "rev", "sgt",
+];
+
+fn is_terminator(c: char) -> bool {
+    matches!(c, '.' | '?' | '!' | ';')
+}
+
+fn is_closer(c: char) -> bool {
+    matches!(c, '"' | '\'' | '\u{201D}' | '\u{2019}' | ')' | ']' | '»')
+}
+
+/// Like [`split_sentences`], but a terminator only ends a sentence when it is followed by
+/// whitespace or the end of the text, and outside parentheses. Closing quotes and brackets
+/// after it stay with the sentence, as does the terminator. A period does not end a sentence after a known
+/// abbreviation ("Dr."), a dotted one ("e.g."), an initial ("J."), or before a lowercase word.
+pub fn split_sentences_robust(text: &str) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut sentences = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0usize;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+        if !is_terminator(c) || depth > 0 {
+            current.push(c);
+            i += 1;
+            continue;
+        }
+
+        let mut end = i;
+        while end < chars.len() && is_terminator(chars[end]) {
+            end += 1;
+        }
+        let terminators: String = chars[i..end].iter().collect();
+        let mut closers = String::new();
+        while end < chars.len() && is_closer(chars[end]) {
+            closers.push(chars[end]);
+            end += 1;
+        }
+
+        let at_boundary = chars.get(end).is_none_or(|c| c.is_whitespace());
+        let ends = at_boundary && ends_sentence(&current, &terminators, &chars[end..]);
+        current.push_str(&terminators);
+        current.push_str(&closers);
+        if ends {
+            sentences.push(std::mem::take(&mut current));
+        }
+        i = end;
+    }
+    sentences.push(current);
+
+    sentences.retain(|s| !is_blank_sentence(s));
+    sentences
+}
+
+/// Whether a run of terminators after `before` ends the sentence, given the text after it.
+fn ends_sentence(before: &str, terminators: &str, after: &[char]) -> bool {
+    if terminators.chars().any(|c| c != '.') {
+        return true;
+    }
+    if after
+        .iter()
+        .find(|c| !c.is_whitespace())
+        .is_some_and(|c| c.is_lowercase())
+    {
+        return false;
+    }
+    let word = before
+        .rsplit(char::is_whitespace)
+        .next()
+        .unwrap_or("")
+        .trim_start_matches(|c: char| !c.is_alphanumeric());
+    let initial = word.len() == 1 && word != "I" && word.chars().all(|c| c.is_uppercase());
+    let dotted = word.contains('.')
+        && word
+            .split('.')
+            .all(|part| part.chars().count() == 1 && part.chars().all(char::is_alphabetic));
+    !(initial || dotted || ABBREVIATIONS.contains(&word.to_lowercase().as_str()))
+}
+
+/// The espeak language of a letter whose script identifies one; `None` for Latin
+/// letters, which are left to the request's language.
+fn script_language(c: char) -> Option<&'static str> {
+    match c as u32 {
+        0x0370..=0x03FF => Some("el"),
+        0x0400..=0x04FF => Some("ru"),
+        0x0590..=0x05FF => Some("he"),
+        0x0600..=0x06FF => Some("ar"),
+        0x0900..=0x097F => Some("hi"),
+        0x3040..=0x30FF => Some("ja"),
+        0x1100..=0x11FF | 0xAC00..=0xD7AF => Some("ko"),
+        0x4E00..=0x9FFF => Some("cmn"),
+        _ => None,
+    }
+}
+
+/// Splits text into runs of one script, returned with the language detected for each
+/// (`None` for Latin text). Spaces, digits and punctuation stay with the preceding run,
+/// and kanji next to kana are read as Japanese.
+pub fn split_language_spans(text: &str) -> Vec<(String, Option<&'static str>)> {
+    let mut spans: Vec<(String, Option<&'static str>)> = Vec::new();
+    for c in text.chars() {
+        let current = spans.last().and_then(|(_, lan)| *lan);
+        let lan = match script_language(c) {
+            _ if !c.is_alphabetic() => current,
+            Some("cmn") if current == Some("ja") => current,
+            lan => lan,
+        };
+        match spans.last_mut() {
+            Some((span, span_lan)) if *span_lan == lan => span.push(c),
+            _ => spans.push((c.to_string(), lan)),
+        }
+    }
+    spans
+}
+
+/// Splits text into segments inside and outside parentheses, returned with whether
+/// each one is a parenthetical aside. Nested parentheses belong to the outer aside,
+/// an unclosed one runs to the end of the text, and the parentheses are dropped.
+/// A comma or terminator after a closing parenthesis stays with the aside, so that it
+/// is read with the intonation of the sentence it belongs to.
+pub fn split_parentheticals(text: &str) -> Vec<(String, bool)> {
+    let mut segments = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0usize;
+
+    for c in text.chars() {
+        match c {
+            '(' => {
+                if depth == 0 {
+                    segments.push((std::mem::take(&mut current), false));
+                }
+                depth += 1;
+            }
+            ')' if depth > 0 => {
+                depth -= 1;
+                if depth == 0 {
+                    segments.push((std::mem::take(&mut current), true));
+                }
+            }
+            _ => current.push(c),
+        }
+    }
+    segments.push((current, depth > 0));
+
+    // Punctuation right after a closing parenthesis continues the segment before it.
+    for i in 1..segments.len() {
+        let segment = segments[i].0.trim_start();
+        let rest = segment.trim_start_matches(|c: char| TERMINAL_MARKS.contains(&c) || OPEN_MARKS.contains(&c));
+        let marks = segment[..segment.len() - rest.len()].to_string();
+        segments[i].0 = rest.to_string();
+        segments[i - 1].0 = format!("{}{}", segments[i - 1].0.trim_end(), marks);
+    }
+
+    segments
+        .into_iter()
+        .map(|(segment, aside)| (segment.trim().to_string(), aside))
+        .filter(|(segment, _)| has_speakable_content(segment))
+        .collect()
+}
+
+/// Marks that end a sentence in the supported languages.
+const TERMINAL_MARKS: [char; 9] = ['.', '!', '?', '…', '。', '！', '？', '।', '؟'];
+/// Marks that leave a sentence open and are replaced when a full stop is added.
+const OPEN_MARKS: [char; 9] = [',', ';', ':', '，', '、', '،', '；', '：', '؛'];
+
+/// The full stop of a language, keyed by espeak language code prefix.
+fn full_stop(lan: &str) -> char {
+    let lan = lan.split(['-', '_']).next().unwrap_or(lan);
+    match lan {
+        "zh" | "cmn" | "yue" | "ja" => '。',
+        "hi" | "mr" | "ne" | "bn" => '।',
+        _ => '.',
+    }
+}
+
+/// Ends text that lacks terminal punctuation with the language's full stop, so that
+/// the model gives it a closing intonation instead of trailing off. A trailing comma,
+/// semicolon or colon is replaced, and closing quotes or brackets after a terminator
+/// count as ending the sentence. `None` if nothing needs to be added.
+pub fn add_terminal_punctuation(text: &str, lan: &str) -> Option<String> {
+    let text = text.trim_end();
+    if !has_speakable_content(text) || text.trim_end_matches(is_closer).ends_with(TERMINAL_MARKS) {
+        return None;
+    }
+    let mut terminated = text.trim_end_matches(OPEN_MARKS).trim_end().to_string();
+    terminated.push(full_stop(lan));
+    Some(terminated)
+}
+
+/// Cuts text after its first `max_words` words, dropping any comma, dash or similar
+/// mark left at the end so that it reads as a clean cutoff. Tokens without letters or
+/// digits, such as a dash, are not counted as words. Returns the truncated text and the
+/// number of words dropped, or `None` if the text has no more than `max_words` words.
+pub fn truncate_words(text: &str, max_words: usize) -> Option<(String, usize)> {
+    let is_word = |token: &str| token.chars().any(char::is_alphanumeric);
+    let total = text.split_whitespace().filter(|token| is_word(token)).count();
+    if total <= max_words {
+        return None;
+    }
+    let mut kept = Vec::new();
+    let mut words = 0;
+    for token in text.split_whitespace() {
+        if is_word(token) {
+            if words == max_words {
+                break;
+            }
+            words += 1;
+        }
+        kept.push(token);
+    }
+    // Keep terminal punctuation and closing quotes, drop commas, dashes and the like.
+    let keep = |c: char| c.is_alphanumeric() || TERMINAL_MARKS.contains(&c) || is_closer(c);
+    let truncated = kept.join(" ").trim_end_matches(|c: char| !keep(c)).to_string();
+    Some((truncated, total - max_words))
+}
+
+/// Comma characters used by a language, keyed by espeak language code prefix.
+fn comma_chars(lan: &str) -> &'static [char] {
+    let lan = lan.split(['-', '_']).next().unwrap_or(lan);
+    match lan {
+        "zh" | "cmn" | "yue" => &[',', '，', '、'],
+        "ja" => &[',', '，', '、'],
+        "ar" | "fa" | "ur" => &[',', '،'],
+        _ => &[','],
+    }
+}
+
+/// Splits text after each comma of the given language, keeping the comma with the
+/// preceding part. Digit-group separators such as in "1,000" are not split on.
+pub fn split_at_commas(text: &str, lan: &str) -> Vec<String> {
+    let commas = comma_chars(lan);
+    let chars: Vec<char> = text.chars().collect();
+    let mut parts = Vec::new();
+    let mut current = String::new();
+
+    for (i, &c) in chars.iter().enumerate() {
+        current.push(c);
+        let digit_group = c == ','
+            && i > 0
+            && chars[i - 1].is_ascii_digit()
+            && chars.get(i + 1).is_some_and(|n| n.is_ascii_digit());
+        if commas.contains(&c) && !digit_group {
+            parts.push(std::mem::take(&mut current));
+        }
+    }
+    parts.push(current);
+
+    parts
+        .into_iter()
+        .map(|p| p.trim().to_string())
+        .filter(|p| p.chars().any(|c| c.is_alphanumeric()))
+        .collect()
+}
+
+/// Punctuation that can be followed by a configured pause.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PauseMark {
+    /// End of a sentence: `.`, `!` or `?`.
+    Period,
+    Comma,
+    Semicolon,
+    Colon,
+    /// An em or en dash, or a hyphen with spaces around it.
+    Dash,
+    /// `...` or `…`.
+    Ellipsis,
+}
+
+/// Pause durations in milliseconds after each kind of punctuation, per language.
+///
+/// Loaded from JSON keyed by espeak language code or its prefix, e.g.
+/// `{"en": {"period": 450, "comma": 120}, "ja": {"comma": 90}}`. Marks a language
+/// does not set fall back to built-in defaults for that language.
+#[derive(Clone, Debug, Default)]
+pub struct PauseMap {
+    languages: HashMap<String, HashMap<PauseMark, u32>>,
+}
+
+impl PauseMap {
+    pub fn from_json(value: serde_json::Value) -> Result<Self, String> {
+        let languages =
+            serde_json::from_value(value).map_err(|e| format!("invalid pause map: {}", e))?;
+        Ok(PauseMap { languages })
+    }
+
+    pub fn pause_ms(&self, lan: &str, mark: PauseMark) -> u32 {
+        let prefix = lan.split(['-', '_']).next().unwrap_or(lan);
+        [lan, prefix]
+            .iter()
+            .find_map(|key| self.languages.get(*key)?.get(&mark).copied())
+            .unwrap_or_else(|| default_pause_ms(prefix, mark))
+    }
+}
+
+/// Built-in pause after each mark. CJK text is punctuated more densely, so its
+/// commas and periods get shorter pauses.
+fn default_pause_ms(lan: &str, mark: PauseMark) -> u32 {
+    let cjk = matches!(lan, "zh" | "cmn" | "yue" | "ja");
+    match mark {
+        PauseMark::Period if cjk => 300,
+        PauseMark::Period => 400,
+        PauseMark::Comma if cjk => 100,
+        PauseMark::Comma => 150,
+        PauseMark::Semicolon | PauseMark::Colon => 250,
+        PauseMark::Dash => 200,
+        PauseMark::Ellipsis => 500,
+    }
+}
+
+/// Splits text after each punctuation mark that gets a pause, returning each phrase with
+/// the mark that ends it. Commas follow the language as in [`split_at_commas`], and a
+/// period only counts where [`split_sentences_robust`] would end a sentence, so "Dr." or
+/// "3.14" do not pause. Phrases without speakable content are dropped.
+pub fn split_at_pauses(text: &str, lan: &str) -> Vec<(String, Option<PauseMark>)> {
+    let commas = comma_chars(lan);
+    let chars: Vec<char> = text.chars().collect();
+    let mut phrases = Vec::new();
+    let mut current = String::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let followed_by_space = |end: usize| chars.get(end).is_none_or(|c| c.is_whitespace());
+        let mut end = i + 1;
+        let mark = match c {
+            '.' | '!' | '?' | '…' | '。' | '！' | '？' => {
+                while end < chars.len() && matches!(chars[end], '.' | '!' | '?' | '…' | '。' | '！' | '？') {
+                    end += 1;
+                }
+                let terminators: String = chars[i..end].iter().collect();
+                while end < chars.len() && is_closer(chars[end]) {
+                    end += 1;
+                }
+                let full_width = terminators.chars().any(|c| matches!(c, '。' | '！' | '？'));
+                if terminators.contains('…') || terminators.starts_with("..") {
+                    followed_by_space(end).then_some(PauseMark::Ellipsis)
+                } else if (full_width || followed_by_space(end))
+                    && ends_sentence(&current, &terminators, &chars[end..])
+                {
+                    Some(PauseMark::Period)
+                } else {
+                    None
+                }
+            }
+            ';' | ':' if followed_by_space(end) => Some(if c == ';' { PauseMark::Semicolon } else { PauseMark::Colon }),
+            '；' => Some(PauseMark::Semicolon),
+            '：' => Some(PauseMark::Colon),
+            '—' | '–' => Some(PauseMark::Dash),
+            '-' if i > 0 && chars[i - 1] == ' ' && followed_by_space(end) => Some(PauseMark::Dash),
+            _ if commas.contains(&c) => {
+                let digit_group = c == ','
+                    && i > 0
+                    && chars[i - 1].is_ascii_digit()
+                    && chars.get(end).is_some_and(|n| n.is_ascii_digit());
+                (!digit_group).then_some(PauseMark::Comma)
+            }
+            _ => None,
+        };
+        current.extend(&chars[i..end]);
+        if mark.is_some() {
+            phrases.push((std::mem::take(&mut current), mark));
+        }
+        i = end;
+    }
+    phrases.push((current, None));
+
+    phrases
+        .into_iter()
+        .map(|(phrase, mark)| (phrase.trim().to_string(), mark))
+        .filter(|(phrase, _)| has_speakable_content(phrase))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exceptions() {
+        let exceptions = Exceptions::from_json(serde_json::json!([
+            {"pattern": r"\bv\d+(\.\d+)+\b"},
+            {"pattern": r"\bk(\d+)s\b", "spell": "kubernetes $1"},
+        ]))
+        .unwrap();
+        assert_eq!(
+            exceptions.apply("Soooo v1.2.3 runs on k8s!!!", collapse_elongation),
+            "So v1.2.3 runs on kubernetes 8!"
+        );
+        assert_eq!(Exceptions::default().apply("Soooo", collapse_elongation), "So");
+
+        assert!(Exceptions::from_json(serde_json::json!([{"pattern": "("}])).is_err());
+        assert!(Exceptions::from_json(serde_json::json!([{"pattern": "a*"}])).is_err());
+        assert!(Exceptions::from_json(serde_json::json!({"pattern": "a"})).is_err());
+    }
+
+    #[test]
+    fn test_split_at_commas() {
+        assert_eq!(
+            split_at_commas("eggs, milk, bread, and butter.", "en-us"),
+            vec!["eggs,", "milk,", "bread,", "and butter."]
+        );
+        assert_eq!(split_at_commas("It costs 1,000 dollars.", "en-us"), vec!["It costs 1,000 dollars."]);
+        assert_eq!(split_at_commas("苹果、香蕉，橙子", "zh"), vec!["苹果、", "香蕉，", "橙子"]);
+        // Ideographic commas are not commas in English text.
+        assert_eq!(split_at_commas("a、b", "en"), vec!["a、b"]);
+    }
+
+    #[test]
+    fn test_degenerate_inputs() {
+        for input in ["!!!", "...", " ?! ", "", "—", "*_*"] {
+            assert!(!has_speakable_content(input), "{:?}", input);
+        }
+        for input in ["3.14159", "42", "1,000!", "a", "½"] {
+            assert!(has_speakable_content(input), "{:?}", input);
+        }
+
+        assert_eq!(split_sentences("3.14159"), vec!["3.14159"]);
+        assert_eq!(split_sentences("Pi is 3.14. Tau is 6.28!"), vec!["Pi is 3.14.", " Tau is 6.28!"]);
+        assert_eq!(split_sentences("Version 2. 5 more."), vec!["Version 2.", " 5 more."]);
+        assert!(split_sentences("!!!").is_empty());
+        assert_eq!(split_sentences("Wait... what"), vec!["Wait...", " what"]);
+
+        assert!(ends_with_terminator("He said \"Stop!\" "));
+        assert!(ends_with_terminator("こんにちは。"));
+        assert!(ends_with_terminator("Wait…"));
+        assert!(!ends_with_terminator("eggs, milk,"));
+        assert!(!ends_with_terminator("See you tomorrow"));
+    }
+
+    #[test]
+    fn test_sentence_splitters() {
+        fn split(splitter: SentenceSplitter, text: &str) -> Vec<String> {
+            splitter.split(text).iter().map(|s| s.trim().to_string()).collect()
+        }
+
+        let corpus: &[(&str, &[&str])] = &[
+            ("Dr. Smith arrived. He sat down.", &["Dr. Smith arrived.", "He sat down."]),
+            ("Mrs. Jones vs. Mr. Brown!", &["Mrs. Jones vs. Mr. Brown!"]),
+            ("Bring a tool, e.g. a hammer. Then start.", &["Bring a tool, e.g. a hammer.", "Then start."]),
+            ("The U.S. economy grew. Prices fell.", &["The U.S. economy grew.", "Prices fell."]),
+            ("Pi is 3.14. Version v1.2 shipped!", &["Pi is 3.14.", "Version v1.2 shipped!"]),
+            ("Visit example.com today.", &["Visit example.com today."]),
+            ("J. R. R. Tolkien wrote it; we read it.", &["J. R. R. Tolkien wrote it;", "we read it."]),
+            ("So do I. Then what?", &["So do I.", "Then what?"]),
+            ("He said \"Stop!\" Then he left.", &["He said \"Stop!\"", "Then he left."]),
+            (
+                "She left (see Fig. 2. It is big.) and came back. Done?!",
+                &["She left (see Fig. 2. It is big.) and came back.", "Done?!"],
+            ),
+            ("Wait... what? Really.", &["Wait... what?", "Really."]),
+            ("Apples, pears, etc. and more. Fine.", &["Apples, pears, etc. and more.", "Fine."]),
+            ("!!!", &[]),
+        ];
+        for (text, expected) in corpus {
+            assert_eq!(split(SentenceSplitter::Robust, text), *expected, "{:?}", text);
+        }
+
+        assert_eq!(
+            split(SentenceSplitter::Simple, "Dr. Smith arrived."),
+            vec!["Dr.", "Smith arrived."]
+        );
+    }
+
+    #[test]
+    fn test_add_terminal_punctuation() {
+        let terminate = |text, lan| add_terminal_punctuation(text, lan);
+        assert_eq!(terminate("See you tomorrow", "en-us"), Some("See you tomorrow.".to_string()));
+        assert_eq!(terminate("See you tomorrow, \n", "en-us"), Some("See you tomorrow.".to_string()));
+        assert_eq!(terminate("He said \"hi\"", "en-us"), Some("He said \"hi\".".to_string()));
+        assert_eq!(terminate("はい、そうです", "ja"), Some("はい、そうです。".to_string()));
+        assert_eq!(terminate("नमस्ते", "hi"), Some("नमस्ते।".to_string()));
+
+        for text in ["Done.", "Really?!", "Wait…", "He said \"hi.\"", "(Yes!)", "そうです。", "", " ?"] {
+            assert_eq!(terminate(text, "en-us"), None, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn test_truncate_words() {
+        assert_eq!(
+            truncate_words("One, two; three four.", 2),
+            Some(("One, two".to_string(), 2))
+        );
+        assert_eq!(
+            truncate_words("Hello there — general Kenobi", 2),
+            Some(("Hello there".to_string(), 2))
+        );
+        assert_eq!(
+            truncate_words("He said \"stop.\" Then he left", 3),
+            Some(("He said \"stop.\"".to_string(), 3))
+        );
+        assert_eq!(truncate_words("Just three words", 3), None);
+        assert_eq!(truncate_words("— Hi —", 1), None);
+    }
+
+    #[test]
+    fn test_split_at_pauses() {
+        let phrase = |text: &str, mark| (text.to_string(), mark);
+        assert_eq!(
+            split_at_pauses("Wait... at 10:30, Dr. Smith said: go; now - or never. Done", "en-us"),
+            vec![
+                phrase("Wait...", Some(PauseMark::Ellipsis)),
+                phrase("at 10:30,", Some(PauseMark::Comma)),
+                phrase("Dr. Smith said:", Some(PauseMark::Colon)),
+                phrase("go;", Some(PauseMark::Semicolon)),
+                phrase("now -", Some(PauseMark::Dash)),
+                phrase("or never.", Some(PauseMark::Period)),
+                phrase("Done", None),
+            ]
+        );
+        assert_eq!(
+            split_at_pauses("はい、そうです。", "ja"),
+            vec![phrase("はい、", Some(PauseMark::Comma)), phrase("そうです。", Some(PauseMark::Period))]
+        );
+        assert_eq!(split_at_pauses("1,000 well-known", "en"), vec![phrase("1,000 well-known", None)]);
+
+        let pauses = PauseMap::from_json(serde_json::json!({"en": {"comma": 90}, "en-gb": {"period": 600}})).unwrap();
+        assert_eq!(pauses.pause_ms("en-us", PauseMark::Comma), 90);
+        assert_eq!(pauses.pause_ms("en-gb", PauseMark::Period), 600);
+        assert_eq!(pauses.pause_ms("en-us", PauseMark::Period), 400);
+        assert_eq!(pauses.pause_ms("ja", PauseMark::Comma), 100);
+        assert!(PauseMap::from_json(serde_json::json!({"en": {"bang": 90}})).is_err());
+    }
+
+    #[test]
+    fn test_split_language_spans() {
+        let spans = |text| -> Vec<(String, Option<&str>)> { split_language_spans(text) };
+        assert_eq!(
+            spans("Say привет, мир! to them."),
+            vec![
+                ("Say ".to_string(), None),
+                ("привет, мир! ".to_string(), Some("ru")),
+                ("to them.".to_string(), None),
+            ]
+        );
+        assert_eq!(spans("ひらがなと漢字"), vec![("ひらがなと漢字".to_string(), Some("ja"))]);
+        assert_eq!(spans("Plain English, 42."), vec![("Plain English, 42.".to_string(), None)]);
+        assert!(spans("").is_empty());
+    }
+
+    #[test]
+    fn test_split_parentheticals() {
+        let segment = |s: &str, aside| (s.to_string(), aside);
+        assert_eq!(
+            split_parentheticals("The castle (built in 1420 (or so)) still stands."),
+            vec![
+                segment("The castle", false),
+                segment("built in 1420 or so", true),
+                segment("still stands.", false),
+            ]
+        );
+        assert_eq!(split_parentheticals("No asides here."), vec![segment("No asides here.", false)]);
+        assert_eq!(split_parentheticals("Open (never closed"), vec![segment("Open", false), segment("never closed", true)]);
+        assert_eq!(split_parentheticals("Stray ) and ()"), vec![segment("Stray ) and", false)]);
+        assert_eq!(
+            split_parentheticals("We left (briefly), and came back (twice)."),
+            vec![
+                segment("We left", false),
+                segment("briefly,", true),
+                segment("and came back", false),
+                segment("twice.", true),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_collapse_elongation() {
+        assert_eq!(collapse_elongation("soooo goooood!!!"), "so good!");
+        assert_eq!(collapse_elongation("Nooooo, heeeey, yesss"), "No, hey, yes");
+        assert_eq!(collapse_elongation("Wait... what?!?! ok"), "Wait... what?!?! ok");
+        // Ordinary double letters and digit groups are untouched.
+        assert_eq!(collapse_elongation("Look, a bookkeeper paid 1000"), "Look, a bookkeeper paid 1000");
+        // All-caps words, roman numerals, single-letter words and other scripts keep their runs.
+        assert_eq!(
+            collapse_elongation("World War III, an AAA battery, XXX"),
+            "World War III, an AAA battery, XXX"
+        );
+        assert_eq!(collapse_elongation("chapter viii of the www"), "chapter viii of the www");
+        assert_eq!(collapse_elongation("zzz 哈哈哈"), "zzz 哈哈哈");
+    }
+
+    #[test]
+    fn test_normalize_links() {
+        let text = "See https://www.example.com/docs/ or mail jane.doe@example.co.uk.";
+        assert_eq!(
+            normalize_links(text, LinkPolicy::Spoken),
+            "See example dot com slash docs or mail jane dot doe at example dot co dot uk."
+        );
+        assert_eq!(
+            normalize_links(text, LinkPolicy::Summarize),
+            "See link or mail email address."
+        );
+        assert_eq!(normalize_links(text, LinkPolicy::Omit), "See or mail.");
+        assert_eq!(
+            normalize_links("Open www.rust-lang.org?q=1, then...", LinkPolicy::Spoken),
+            "Open rust dash lang dot org, then..."
+        );
+    }
+}
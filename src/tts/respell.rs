@@ -0,0 +1,253 @@
+use espeak_rs::text_to_phonemes;
+use serde::Serialize;
+
+/// A word with its phonemes and a respelling that readers without IPA can follow.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct WordRespelling {
+    pub word: String,
+    pub phonemes: String,
+    /// Syllables joined by hyphens, the stressed one in capitals, e.g. `tuh-MAY-toh`.
+    pub respelling: String,
+}
+
+/// Respells every word of the text, skipping punctuation-only words and words that
+/// cannot be phonemized.
+pub fn respell_text(text: &str, lan: &str) -> Vec<WordRespelling> {
+    text.split_whitespace()
+        .map(|word| word.trim_matches(|c: char| !c.is_alphanumeric()))
+        .filter(|word| !word.is_empty())
+        .filter_map(|word| {
+            let phonemes = text_to_phonemes(word, lan, None, true, false).ok()?.join("");
+            Some(WordRespelling {
+                word: word.to_string(),
+                respelling: respell(&phonemes),
+                phonemes,
+            })
+        })
+        .collect()
+}
+
+/// IPA sequences, as written by espeak, with their respelling; longest first so that
+/// diphthongs win over their parts. `aɪ` is handled separately.
+const VOWELS: [(&str, &str); 34] = [
+    ("eɪ", "ay"),
+    ("ɔɪ", "oy"),
+    ("oʊ", "oh"),
+    ("əʊ", "oh"),
+    ("aʊ", "ow"),
+    ("ɪə", "eer"),
+    ("eə", "air"),
+    ("ʊə", "oor"),
+    ("iː", "ee"),
+    ("uː", "oo"),
+    ("ɑː", "ah"),
+    ("ɔː", "aw"),
+    ("ɜː", "ur"),
+    ("oː", "oh"),
+    ("æ", "a"),
+    ("a", "a"),
+    ("ɑ", "ah"),
+    ("ɒ", "o"),
+    ("ʌ", "uh"),
+    ("ə", "uh"),
+    ("ɐ", "uh"),
+    ("ᵻ", "ih"),
+    ("ɪ", "ih"),
+    ("ɛ", "eh"),
+    ("e", "eh"),
+    ("i", "ee"),
+    ("ʊ", "uu"),
+    ("u", "oo"),
+    ("ɔ", "aw"),
+    ("o", "oh"),
+    ("ɚ", "er"),
+    ("ɝ", "ur"),
+    ("ɜ", "ur"),
+    ("y", "ew"),
+];
+
+const CONSONANTS: [(&str, &str); 30] = [
+    ("tʃ", "ch"),
+    ("dʒ", "j"),
+    ("p", "p"),
+    ("b", "b"),
+    ("t", "t"),
+    ("d", "d"),
+    ("k", "k"),
+    ("g", "g"),
+    ("ɡ", "g"),
+    ("f", "f"),
+    ("v", "v"),
+    ("θ", "th"),
+    ("ð", "dh"),
+    ("s", "s"),
+    ("z", "z"),
+    ("ʃ", "sh"),
+    ("ʒ", "zh"),
+    ("h", "h"),
+    ("x", "kh"),
+    ("m", "m"),
+    ("n", "n"),
+    ("ŋ", "ng"),
+    ("l", "l"),
+    ("ɹ", "r"),
+    ("r", "r"),
+    ("j", "y"),
+    ("w", "w"),
+    // The American flap in "water" and "tomato".
+    ("ɾ", "t"),
+    ("ʔ", ""),
+    ("ɬ", "hl"),
+];
+
+/// `aɪ` reads as "y" after a consonant ("my" → MY) and as "eye" on its own.
+const AI: &str = "aɪ";
+
+/// Consonants that can follow another in a syllable onset, as in "pr", "bl" and "tw".
+const ONSET_GLIDES: [&str; 4] = ["r", "l", "w", "y"];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Stress {
+    Primary,
+    Secondary,
+}
+
+#[derive(Debug)]
+enum Phone {
+    Vowel { spelling: &'static str, stress: Option<Stress> },
+    Consonant(&'static str),
+}
+
+fn parse_phones(phonemes: &str) -> Vec<Phone> {
+    let mut phones = Vec::new();
+    let mut stress = None;
+    let mut rest = phonemes;
+    while let Some(c) = rest.chars().next() {
+        match c {
+            'ˈ' => stress = Some(Stress::Primary),
+            'ˌ' => stress = Some(Stress::Secondary),
+            _ => {
+                if let Some(tail) = rest.strip_prefix(AI) {
+                    phones.push(Phone::Vowel { spelling: AI, stress: stress.take() });
+                    rest = tail;
+                    continue;
+                }
+                if let Some((ipa, spelling)) = VOWELS.iter().find(|(ipa, _)| rest.starts_with(ipa)) {
+                    phones.push(Phone::Vowel { spelling, stress: stress.take() });
+                    rest = &rest[ipa.len()..];
+                    continue;
+                }
+                if let Some((ipa, spelling)) = CONSONANTS.iter().find(|(ipa, _)| rest.starts_with(ipa)) {
+                    if !spelling.is_empty() {
+                        phones.push(Phone::Consonant(spelling));
+                    }
+                    rest = &rest[ipa.len()..];
+                    continue;
+                }
+                // Length marks, tie bars and other symbols without a respelling.
+            }
+        }
+        rest = &rest[c.len_utf8()..];
+    }
+    phones
+}
+
+/// Respells espeak phonemes for a word or a few, syllable by syllable, e.g.
+/// `təmˈeɪɾoʊ` → `tuh-MAY-toh`. Between two vowels, a single consonant starts the
+/// next syllable, as do a consonant and a following r, l, w or y; any others close
+/// the previous syllable.
+pub fn respell(phonemes: &str) -> String {
+    phonemes
+        .split_whitespace()
+        .map(respell_word)
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn respell_word(phonemes: &str) -> String {
+    let phones = parse_phones(phonemes);
+    let vowels: Vec<usize> = phones
+        .iter()
+        .enumerate()
+        .filter(|(_, phone)| matches!(phone, Phone::Vowel { .. }))
+        .map(|(i, _)| i)
+        .collect();
+    if vowels.is_empty() {
+        return phones.iter().map(consonant_spelling).collect();
+    }
+
+    // Where each syllable after the first starts.
+    let mut starts = vec![0];
+    for pair in vowels.windows(2) {
+        let (previous, next) = (pair[0], pair[1]);
+        let consonants = &phones[previous + 1..next];
+        let onset = match consonants {
+            [.., first, Phone::Consonant(second)]
+                if ONSET_GLIDES.contains(second)
+                    && !matches!(first, Phone::Consonant(c) if ONSET_GLIDES.contains(c) || c == second) =>
+            {
+                2
+            }
+            [] => 0,
+            _ => 1,
+        };
+        starts.push(next - onset);
+    }
+    starts.push(phones.len());
+
+    starts
+        .windows(2)
+        .map(|bounds| respell_syllable(&phones[bounds[0]..bounds[1]]))
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+fn consonant_spelling(phone: &Phone) -> &'static str {
+    match phone {
+        Phone::Consonant(spelling) => spelling,
+        Phone::Vowel { .. } => "",
+    }
+}
+
+fn respell_syllable(phones: &[Phone]) -> String {
+    let mut syllable = String::new();
+    let mut stress = None;
+    for (i, phone) in phones.iter().enumerate() {
+        match phone {
+            Phone::Consonant(spelling) => syllable.push_str(spelling),
+            Phone::Vowel { spelling, stress: vowel_stress } => {
+                stress = stress.or(*vowel_stress);
+                syllable.push_str(match *spelling {
+                    AI if i > 0 => "y",
+                    AI => "eye",
+                    spelling => spelling,
+                });
+            }
+        }
+    }
+    match stress {
+        Some(Stress::Primary) => syllable.to_uppercase(),
+        _ => syllable,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_respell() {
+        assert_eq!(respell("təmˈeɪɾoʊ"), "tuh-MAY-toh");
+        assert_eq!(respell("həlˈoʊ wˈɜːld"), "huh-LOH WURLD");
+        assert_eq!(respell("sˈɪstəm"), "SIHS-tuhm");
+        assert_eq!(respell("əpɹˈuːv"), "uh-PROOV");
+        assert_eq!(respell("wˈɔːɾɚ"), "WAW-ter");
+        assert_eq!(respell("mˈaɪ ˈaɪs"), "MY EYES");
+        assert_eq!(respell("fənˈɛɾɪk"), "fuh-NEH-tihk");
+        assert_eq!(respell("ˌɪntɚnˈæʃənəl"), "ihn-ter-NA-shuh-nuhl");
+        assert_eq!(respell("tʃˈɜːtʃ dʒˈʌdʒ"), "CHURCH JUHJ");
+        assert_eq!(respell("hmm"), "hmm");
+        assert_eq!(respell(""), "");
+    }
+}
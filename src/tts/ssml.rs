@@ -0,0 +1,228 @@
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+
+lazy_static! {
+    static ref SAY_AS_RE: Regex = Regex::new(
+        r#"(?is)<say-as\s+interpret-as\s*=\s*(?:"([^"]*)"|'([^']*)')[^>]*>(.*?)</say-as\s*>"#
+    )
+    .unwrap();
+}
+
+const DIGITS: [&str; 10] = ["zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine"];
+
+const LETTERS: [&str; 26] = [
+    "ay", "bee", "see", "dee", "ee", "eff", "gee", "aitch", "eye", "jay", "kay", "el", "em", "en", "oh",
+    "pee", "cue", "ar", "ess", "tee", "you", "vee", "double you", "ex", "why", "zee",
+];
+
+const ONES: [&str; 20] = [
+    "zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine", "ten", "eleven",
+    "twelve", "thirteen", "fourteen", "fifteen", "sixteen", "seventeen", "eighteen", "nineteen",
+];
+
+const TENS: [&str; 10] = ["", "", "twenty", "thirty", "forty", "fifty", "sixty", "seventy", "eighty", "ninety"];
+
+/// Ordinals up to 999,999,999 are written out.
+const MAX_ORDINAL: u64 = 999_999_999;
+
+/// Replaces each `<say-as interpret-as="...">` element with its content, rewritten so
+/// that it is read as intended:
+///
+/// - `characters` (or `spell-out`) spells it letter by letter and digit by digit, e.g.
+///   a confirmation code;
+/// - `ordinal` reads a number like `21` or `21st` as "twenty-first";
+/// - `telephone` reads the digits one by one, pausing between groups.
+///
+/// Other `interpret-as` values, and content that does not fit, are read as usual.
+/// Letter and number names are English; other languages get the characters spaced out
+/// and numbers unchanged. No other SSML is interpreted.
+pub fn expand_say_as(text: &str, lan: &str) -> String {
+    let english = lan.starts_with("en");
+    SAY_AS_RE
+        .replace_all(text, |caps: &Captures| {
+            let interpret_as = caps.get(1).or(caps.get(2)).map_or("", |m| m.as_str());
+            let content = decode_entities(caps[3].trim());
+            let expanded = match interpret_as.trim().to_ascii_lowercase().as_str() {
+                "characters" | "spell-out" => Some(spell_characters(&content, lan)),
+                "ordinal" if english => ordinal(&content),
+                "telephone" => Some(telephone(&content, english)),
+                _ => None,
+            };
+            expanded.unwrap_or(content)
+        })
+        .into_owned()
+}
+
+/// The predefined XML entities, which may appear inside an element.
+fn decode_entities(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Names each letter and digit. Spaces, hyphens and similar separators become pauses,
+/// so that grouped codes like `ABC-123` keep their grouping.
+fn spell_characters(text: &str, lan: &str) -> String {
+    let english = lan.starts_with("en");
+    let groups = text.split(|c: char| c.is_whitespace() || matches!(c, '-' | '.' | '_' | '/'));
+    let spelled: Vec<String> = groups
+        .map(|group| {
+            group
+                .chars()
+                .filter(|c| c.is_alphanumeric())
+                .map(|c| match c.to_ascii_lowercase() {
+                    'z' if lan.starts_with("en-gb") => "zed".to_string(),
+                    c @ 'a'..='z' if english => LETTERS[(c as u8 - b'a') as usize].to_string(),
+                    c @ '0'..='9' if english => DIGITS[(c as u8 - b'0') as usize].to_string(),
+                    c => c.to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .filter(|group| !group.is_empty())
+        .collect();
+    spelled.join(", ")
+}
+
+/// Reads digits one at a time, with a pause wherever the number is grouped by spaces,
+/// hyphens, dots or parentheses, e.g. `+1 (555) 123-4567`.
+fn telephone(text: &str, english: bool) -> String {
+    let mut groups: Vec<Vec<String>> = vec![Vec::new()];
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        let word = match c {
+            '0'..='9' if english => DIGITS[(c as u8 - b'0') as usize].to_string(),
+            '0'..='9' => c.to_string(),
+            '+' if english => "plus".to_string(),
+            '+' => c.to_string(),
+            'x' | 'X' if english => {
+                // An extension, written as `x123` or `ext. 123`.
+                while chars.peek().is_some_and(|c| c.is_alphabetic() || *c == '.') {
+                    chars.next();
+                }
+                groups.push(Vec::new());
+                "extension".to_string()
+            }
+            'e' | 'E' if english && chars.peek().is_some_and(|c| matches!(c, 'x' | 'X')) => continue,
+            _ => {
+                if groups.last().is_some_and(|group| !group.is_empty()) {
+                    groups.push(Vec::new());
+                }
+                continue;
+            }
+        };
+        groups.last_mut().expect("at least one group").push(word);
+    }
+    groups
+        .iter()
+        .filter(|group| !group.is_empty())
+        .map(|group| group.join(" "))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// `21`, `21st` or `1,000th` read in words, e.g. "twenty-first". `None` for anything else.
+fn ordinal(text: &str) -> Option<String> {
+    let lower = text.to_ascii_lowercase();
+    let digits = ["st", "nd", "rd", "th"]
+        .iter()
+        .find_map(|suffix| lower.strip_suffix(suffix))
+        .unwrap_or(&lower)
+        .replace(',', "");
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let n: u64 = digits.parse().ok().filter(|&n| n <= MAX_ORDINAL)?;
+    let cardinal = cardinal(n);
+    // Only the last word takes the ordinal form: "one hundred twenty-one" -> "... twenty-first".
+    let split = cardinal.rfind([' ', '-']).map_or(0, |i| i + 1);
+    let (head, last) = cardinal.split_at(split);
+    let last = match last {
+        "one" => "first".to_string(),
+        "two" => "second".to_string(),
+        "three" => "third".to_string(),
+        "five" => "fifth".to_string(),
+        "eight" => "eighth".to_string(),
+        "nine" => "ninth".to_string(),
+        "twelve" => "twelfth".to_string(),
+        word if word.ends_with('y') => format!("{}ieth", &word[..word.len() - 1]),
+        word => format!("{}th", word),
+    };
+    Some(format!("{}{}", head, last))
+}
+
+/// A number below a billion in words, e.g. 1021 -> "one thousand twenty-one".
+fn cardinal(n: u64) -> String {
+    if n < 20 {
+        return ONES[n as usize].to_string();
+    }
+    if n < 100 {
+        return match n % 10 {
+            0 => TENS[(n / 10) as usize].to_string(),
+            ones => format!("{}-{}", TENS[(n / 10) as usize], ONES[ones as usize]),
+        };
+    }
+    let (unit, name) = match n {
+        _ if n < 1000 => (100, "hundred"),
+        _ if n < 1_000_000 => (1000, "thousand"),
+        _ => (1_000_000, "million"),
+    };
+    match n % unit {
+        0 => format!("{} {}", cardinal(n / unit), name),
+        rest => format!("{} {} {}", cardinal(n / unit), name, cardinal(rest)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_say_as() {
+        let expand = |text| expand_say_as(text, "en-us");
+        assert_eq!(
+            expand(r#"Your code is <say-as interpret-as="characters">AB7-Z</say-as>."#),
+            "Your code is ay bee seven, zee."
+        );
+        assert_eq!(expand_say_as("<say-as interpret-as='spell-out'>z</say-as>", "en-gb"), "zed");
+        assert_eq!(
+            expand(r#"the <say-as interpret-as="ordinal">21</say-as> and <SAY-AS INTERPRET-AS="ordinal">112th</SAY-AS>"#),
+            "the twenty-first and one hundred twelfth"
+        );
+        assert_eq!(
+            expand(r#"Call <say-as interpret-as="telephone">+1 (555) 123-4567 ext. 89</say-as>"#),
+            "Call plus one, five five five, one two three, four five six seven, extension, eight nine"
+        );
+        // Unsupported values and content that does not fit are read as usual.
+        assert_eq!(expand(r#"<say-as interpret-as="date">1/2/2024</say-as>"#), "1/2/2024");
+        assert_eq!(expand(r#"<say-as interpret-as="ordinal">first</say-as>"#), "first");
+        assert_eq!(expand(r#"<say-as interpret-as="characters">R&amp;D</say-as>"#), "ar dee");
+        assert_eq!(expand("no markup, 21st"), "no markup, 21st");
+        // Outside English, characters are spaced out and numbers left unchanged.
+        assert_eq!(expand_say_as(r#"<say-as interpret-as="characters">AB7</say-as>"#, "de"), "a b 7");
+        assert_eq!(expand_say_as(r#"<say-as interpret-as="ordinal">3</say-as>"#, "de"), "3");
+    }
+
+    #[test]
+    fn test_ordinals() {
+        let cases = [
+            ("1st", "first"),
+            ("3", "third"),
+            ("11", "eleventh"),
+            ("20", "twentieth"),
+            ("40", "fortieth"),
+            ("99", "ninety-ninth"),
+            ("100", "one hundredth"),
+            ("1,000th", "one thousandth"),
+            ("2005", "two thousand fifth"),
+            ("1000000", "one millionth"),
+        ];
+        for (number, expected) in cases {
+            assert_eq!(ordinal(number).as_deref(), Some(expected), "{}", number);
+        }
+        assert_eq!(ordinal("1000000000"), None);
+        assert_eq!(ordinal("st"), None);
+    }
+}
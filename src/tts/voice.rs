@@ -0,0 +1,153 @@
+use std::fmt;
+
+/// Blends are written as `name.weight` parts joined by `+`, e.g. `af_sky.4+af_nicole.6`.
+const BLEND_SEPARATOR: char = '+';
+const WEIGHT_SEPARATOR: char = '.';
+
+/// A parsed `voice` string: one voice, or a weighted blend of several.
+#[derive(Clone, Debug, PartialEq)]
+pub enum VoiceSpec {
+    Single(String),
+    /// Voice names with their weights in tenths, so `af_sky.4` is 0.4 of `af_sky`.
+    Blend(Vec<(String, f32)>),
+}
+
+impl VoiceSpec {
+    /// Parses a voice string, trimming whitespace around names and weights and
+    /// lowercasing names, and reports what is wrong with malformed blends.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let spec = spec.trim();
+        if spec.is_empty() {
+            return Err("voice is empty".to_string());
+        }
+        if !spec.contains(BLEND_SEPARATOR) {
+            if spec.contains(WEIGHT_SEPARATOR) {
+                return Err(format!(
+                    "voice {:?} has a weight, which is only used in blends like af_sky.4+af_nicole.6",
+                    spec
+                ));
+            }
+            return Ok(VoiceSpec::Single(parse_name(spec)?));
+        }
+
+        let mut parts: Vec<(String, f32)> = Vec::new();
+        for part in spec.split(BLEND_SEPARATOR) {
+            let part = part.trim();
+            let Some((name, weight)) = part.split_once(WEIGHT_SEPARATOR) else {
+                return Err(if part.is_empty() {
+                    "voice blend has an empty part".to_string()
+                } else {
+                    format!("voice blend part {:?} has no weight, e.g. {}.5", part, part)
+                });
+            };
+            let name = parse_name(name.trim())?;
+            let weight = parse_weight(&name, weight.trim())?;
+            if parts.iter().any(|(existing, _)| *existing == name) {
+                return Err(format!("voice {} appears more than once in the blend", name));
+            }
+            parts.push((name, weight));
+        }
+        Ok(VoiceSpec::Blend(parts))
+    }
+}
+
+/// Renders the canonical form, which parses back to the same spec.
+impl fmt::Display for VoiceSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VoiceSpec::Single(name) => f.write_str(name),
+            VoiceSpec::Blend(parts) => {
+                for (i, (name, weight)) in parts.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "{}", BLEND_SEPARATOR)?;
+                    }
+                    write!(f, "{}{}{}", name, WEIGHT_SEPARATOR, weight)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Voice names are lowercase ASCII letters, digits and underscores, like `af_sky`.
+fn parse_name(name: &str) -> Result<String, String> {
+    if name.is_empty() {
+        return Err("voice name is empty".to_string());
+    }
+    let name = name.to_ascii_lowercase();
+    if let Some(c) = name.chars().find(|c| !(c.is_ascii_alphanumeric() || *c == '_')) {
+        return Err(format!("voice name {:?} contains unexpected character {:?}", name, c));
+    }
+    Ok(name)
+}
+
+fn parse_weight(name: &str, weight: &str) -> Result<f32, String> {
+    match weight.parse::<f32>() {
+        Ok(weight) if weight.is_finite() && weight > 0.0 => Ok(weight),
+        Ok(_) => Err(format!("weight of voice {} must be positive", name)),
+        Err(_) => Err(format!("weight of voice {} is not a number: {:?}", name, weight)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blend(parts: &[(&str, f32)]) -> VoiceSpec {
+        VoiceSpec::Blend(parts.iter().map(|(name, weight)| (name.to_string(), *weight)).collect())
+    }
+
+    #[test]
+    fn test_parse_voice_spec() {
+        assert_eq!(VoiceSpec::parse("af_sky"), Ok(VoiceSpec::Single("af_sky".to_string())));
+        assert_eq!(VoiceSpec::parse("  AF_Sky\n"), Ok(VoiceSpec::Single("af_sky".to_string())));
+        assert_eq!(
+            VoiceSpec::parse("af_sky.4+af_nicole.6"),
+            Ok(blend(&[("af_sky", 4.0), ("af_nicole", 6.0)]))
+        );
+        assert_eq!(
+            VoiceSpec::parse(" AF_SKY . 4 + af_nicole.6 "),
+            Ok(blend(&[("af_sky", 4.0), ("af_nicole", 6.0)]))
+        );
+        assert_eq!(VoiceSpec::parse("af_sky.4.5+am_adam.5"), Ok(blend(&[("af_sky", 4.5), ("am_adam", 5.0)])));
+    }
+
+    #[test]
+    fn test_reject_malformed_voice_spec() {
+        let malformed = [
+            "",
+            "   ",
+            "af_sky.4",
+            "af sky",
+            "af-sky",
+            "af_sky.4+",
+            "+af_sky.4",
+            "af_sky.4++am_adam.6",
+            "af_sky+am_adam.6",
+            ".4+am_adam.6",
+            "af_sky.+am_adam.6",
+            "af_sky.x+am_adam.6",
+            "af_sky.0+am_adam.6",
+            "af_sky.-1+am_adam.6",
+            "af_sky.inf+am_adam.6",
+            "af_sky.4+AF_SKY.6",
+        ];
+        for spec in malformed {
+            assert!(VoiceSpec::parse(spec).is_err(), "{:?} should be rejected", spec);
+        }
+        assert_eq!(
+            VoiceSpec::parse("af_sky+am_adam.6").unwrap_err(),
+            "voice blend part \"af_sky\" has no weight, e.g. af_sky.5"
+        );
+    }
+
+    #[test]
+    fn test_voice_spec_round_trips() {
+        for spec in ["af_sky", "af_sky.4+af_nicole.6", "af_sky.4.5+am_adam.5"] {
+            let parsed = VoiceSpec::parse(spec).unwrap();
+            assert_eq!(parsed.to_string(), spec);
+            assert_eq!(VoiceSpec::parse(&parsed.to_string()).unwrap(), parsed);
+        }
+        assert_eq!(VoiceSpec::parse(" AF_Sky.4 + af_nicole.6").unwrap().to_string(), "af_sky.4+af_nicole.6");
+    }
+}
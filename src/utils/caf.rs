@@ -0,0 +1,138 @@
+use std::io::{self, Write};
+
+use crate::utils::dsp::to_i16;
+
+// Linear PCM format flags from the Core Audio Format specification.
+const FORMAT_FLAG_IS_FLOAT: u32 = 1 << 0;
+const FORMAT_FLAG_IS_LITTLE_ENDIAN: u32 = 1 << 1;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CafSampleFormat {
+    Float,
+    Int,
+}
+
+/// Core Audio Format (CAF) header for linear PCM.
+///
+/// All chunk fields are big-endian as required by CAF; the samples themselves are
+/// written little-endian and flagged as such in the `desc` chunk.
+pub struct CafHeader {
+    pub channels: u32,
+    pub sample_rate: u32,
+    pub bits_per_channel: u32,
+    pub sample_format: CafSampleFormat,
+}
+
+impl CafHeader {
+    pub fn new(
+        channels: u32,
+        sample_rate: u32,
+        bits_per_channel: u32,
+        sample_format: CafSampleFormat,
+    ) -> Self {
+        Self {
+            channels,
+            sample_rate,
+            bits_per_channel,
+            sample_format,
+        }
+    }
+
+    fn bytes_per_frame(&self) -> u32 {
+        self.channels * self.bits_per_channel / 8
+    }
+
+    /// Writes the file header, the `desc` chunk and the `data` chunk header.
+    /// If `num_frames` is unknown (streaming), the data chunk size is set to -1,
+    /// which CAF allows for the final chunk of a file.
+    pub fn write_header<W: Write>(&self, writer: &mut W, num_frames: Option<u64>) -> io::Result<()> {
+        // File header
+        writer.write_all(b"caff")?;
+        writer.write_all(&(1u16).to_be_bytes())?; // File version
+        writer.write_all(&(0u16).to_be_bytes())?; // File flags
+
+        // Audio description chunk
+        let mut flags = FORMAT_FLAG_IS_LITTLE_ENDIAN;
+        if self.sample_format == CafSampleFormat::Float {
+            flags |= FORMAT_FLAG_IS_FLOAT;
+        }
+        writer.write_all(b"desc")?;
+        writer.write_all(&(32i64).to_be_bytes())?; // Chunk size
+        writer.write_all(&f64::from(self.sample_rate).to_be_bytes())?;
+        writer.write_all(b"lpcm")?;
+        writer.write_all(&flags.to_be_bytes())?;
+        writer.write_all(&self.bytes_per_frame().to_be_bytes())?; // Bytes per packet
+        writer.write_all(&(1u32).to_be_bytes())?; // Frames per packet
+        writer.write_all(&self.channels.to_be_bytes())?;
+        writer.write_all(&self.bits_per_channel.to_be_bytes())?;
+
+        // Audio data chunk header, the size includes the 4-byte edit count
+        let data_size = match num_frames {
+            Some(frames) => (frames * u64::from(self.bytes_per_frame()) + 4) as i64,
+            None => -1,
+        };
+        writer.write_all(b"data")?;
+        writer.write_all(&data_size.to_be_bytes())?;
+        writer.write_all(&(0u32).to_be_bytes())?; // Edit count
+
+        Ok(())
+    }
+
+    /// Writes samples in the format described by this header.
+    pub fn write_samples<W: Write>(&self, writer: &mut W, samples: &[f32]) -> io::Result<()> {
+        match (self.sample_format, self.bits_per_channel) {
+            (CafSampleFormat::Float, 32) => {
+                for sample in samples {
+                    writer.write_all(&sample.to_le_bytes())?;
+                }
+            }
+            (CafSampleFormat::Int, 16) => {
+                for &sample in samples {
+                    writer.write_all(&to_i16(sample).to_le_bytes())?;
+                }
+            }
+            (format, bits) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("unsupported CAF sample format: {:?} {}-bit", format, bits),
+                ))
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_caf_layout() {
+        let header = CafHeader::new(1, 24000, 32, CafSampleFormat::Float);
+        let samples = [0.0f32, 0.5, -0.5];
+        let mut out = Vec::new();
+        header.write_header(&mut out, Some(samples.len() as u64)).unwrap();
+        header.write_samples(&mut out, &samples).unwrap();
+
+        assert_eq!(&out[0..4], b"caff");
+        assert_eq!(&out[8..12], b"desc");
+        assert_eq!(f64::from_be_bytes(out[20..28].try_into().unwrap()), 24000.0);
+        assert_eq!(&out[28..32], b"lpcm");
+        assert_eq!(u32::from_be_bytes(out[32..36].try_into().unwrap()), 3);
+        assert_eq!(&out[52..56], b"data");
+        assert_eq!(i64::from_be_bytes(out[56..64].try_into().unwrap()), 3 * 4 + 4);
+        assert_eq!(out.len(), 68 + 3 * 4);
+    }
+
+    #[test]
+    fn test_caf_int_samples() {
+        let header = CafHeader::new(1, 24000, 16, CafSampleFormat::Int);
+        let mut out = Vec::new();
+        header.write_header(&mut out, Some(2)).unwrap();
+        header.write_samples(&mut out, &[1.0, -2.0]).unwrap();
+
+        assert_eq!(u32::from_be_bytes(out[32..36].try_into().unwrap()), 2);
+        assert_eq!(i64::from_be_bytes(out[56..64].try_into().unwrap()), 2 * 2 + 4);
+        assert_eq!(&out[68..], &[0xFF, 0x7F, 0x01, 0x80]);
+    }
+}
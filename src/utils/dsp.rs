@@ -0,0 +1,596 @@
+use serde::{Deserialize, Serialize};
+
+/// Level, relative to its ceiling, above which the soft limiter starts compressing.
+const SOFT_LIMIT_THRESHOLD: f32 = 0.9;
+/// Level the soft limiter keeps peaks under when no ceiling is given, in dBFS.
+pub const DEFAULT_LIMITER_CEILING_DBFS: f32 = -1.0;
+
+/// How samples outside `[-1, 1]` are brought into range before they are
+/// converted to integers by the encoders.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClipPolicy {
+    /// Hard-clip each sample to `[-1, 1]`.
+    #[default]
+    Clamp,
+    /// Pass quiet samples through and smoothly compress peaks so that they stay under
+    /// the limiter's ceiling.
+    SoftLimit,
+    /// Scale the whole buffer down so that its peak fits, preserving the waveform shape.
+    Normalize,
+}
+
+/// How far a buffer went beyond the range its clip policy keeps it in.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ClipStats {
+    /// Samples the policy had to change: outside `[-1, 1]`, or for the soft limiter,
+    /// above the level where it starts compressing.
+    pub clipped_samples: usize,
+    /// Largest absolute sample value before the policy was applied.
+    pub peak: f32,
+}
+
+impl ClipStats {
+    /// Counts the samples whose absolute value is above `level`.
+    pub fn measure(samples: &[f32], level: f32) -> Self {
+        ClipStats {
+            clipped_samples: samples.iter().filter(|s| s.abs() > level).count(),
+            peak: peak(samples),
+        }
+    }
+
+    pub fn clipped(&self) -> bool {
+        self.clipped_samples > 0
+    }
+
+    /// Adds the stats of a following buffer, e.g. the next chunk of a stream.
+    pub fn merge(&mut self, other: ClipStats) {
+        self.clipped_samples += other.clipped_samples;
+        self.peak = self.peak.max(other.peak);
+    }
+}
+
+impl ClipPolicy {
+    /// Brings the samples into range and reports how many were out of it. The soft
+    /// limiter keeps sample peaks under `ceiling_dbfs`, or [`DEFAULT_LIMITER_CEILING_DBFS`]
+    /// when `None`; the other policies bring samples to full scale.
+    pub fn apply(self, samples: &mut [f32], ceiling_dbfs: Option<f32>) -> ClipStats {
+        match self {
+            ClipPolicy::Clamp => {
+                let stats = ClipStats::measure(samples, 1.0);
+                for sample in samples.iter_mut() {
+                    *sample = sample.clamp(-1.0, 1.0);
+                }
+                stats
+            }
+            ClipPolicy::SoftLimit => {
+                let ceiling = dbfs_to_gain(ceiling_dbfs.unwrap_or(DEFAULT_LIMITER_CEILING_DBFS));
+                let stats = ClipStats::measure(samples, SOFT_LIMIT_THRESHOLD * ceiling);
+                for sample in samples.iter_mut() {
+                    *sample = soft_limit(*sample, ceiling);
+                }
+                stats
+            }
+            ClipPolicy::Normalize => {
+                let stats = ClipStats::measure(samples, 1.0);
+                let peak = peak(samples);
+                if peak > 1.0 {
+                    for sample in samples.iter_mut() {
+                        *sample /= peak;
+                    }
+                }
+                stats
+            }
+        }
+    }
+}
+
+/// Largest absolute sample value in the buffer.
+pub fn peak(samples: &[f32]) -> f32 {
+    samples.iter().fold(0.0, |peak, s| peak.max(s.abs()))
+}
+
+/// Root mean square level of the buffer; 0 for an empty buffer.
+pub fn rms(samples: &[f32]) -> f32 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+}
+
+/// Largest absolute sample value and the index of its first occurrence.
+pub fn peak_position(samples: &[f32]) -> (f32, usize) {
+    samples
+        .iter()
+        .enumerate()
+        .fold((0.0, 0), |(peak, pos), (i, s)| {
+            if s.abs() > peak {
+                (s.abs(), i)
+            } else {
+                (peak, pos)
+            }
+        })
+}
+
+/// Linear gain of a level in dBFS, e.g. about 0.891 for -1 dBFS.
+pub fn dbfs_to_gain(dbfs: f32) -> f32 {
+    10f32.powf(dbfs / 20.0)
+}
+
+/// Compresses values above the threshold with a tanh knee that approaches, but
+/// never reaches, the ceiling. Below the threshold the signal is untouched.
+fn soft_limit(sample: f32, ceiling: f32) -> f32 {
+    let threshold = SOFT_LIMIT_THRESHOLD * ceiling;
+    let magnitude = sample.abs();
+    if magnitude <= threshold {
+        return sample;
+    }
+    let headroom = ceiling - threshold;
+    let limited = threshold + headroom * ((magnitude - threshold) / headroom).tanh();
+    limited.copysign(sample)
+}
+
+/// Pole of the DC blocker; puts its -3 dB point near 20 Hz at 24 kHz.
+const DC_BLOCKER_POLE: f32 = 0.995;
+
+/// One-pole high-pass filter that removes DC offset, so that it does not waste
+/// headroom or click at the start and end of playback. The filter keeps its state
+/// between calls, so a stream can be processed chunk by chunk.
+#[derive(Debug, Default)]
+pub struct DcBlocker {
+    /// Previous input and output sample; `None` before the first sample.
+    state: Option<(f32, f32)>,
+}
+
+impl DcBlocker {
+    pub fn process(&mut self, samples: &mut [f32]) {
+        for sample in samples.iter_mut() {
+            // Starting from the first sample avoids a step response at the start.
+            let (prev_in, prev_out) = self.state.unwrap_or((*sample, 0.0));
+            let out = *sample - prev_in + DC_BLOCKER_POLE * prev_out;
+            self.state = Some((*sample, out));
+            *sample = out;
+        }
+    }
+}
+
+/// Shape of one equalizer band, after the RBJ Audio EQ Cookbook.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterKind {
+    /// Boost or cut around `freq`.
+    Peaking,
+    /// Boost or cut below `freq`.
+    LowShelf,
+    /// Boost or cut above `freq`.
+    HighShelf,
+    /// Remove content below `freq`.
+    HighPass,
+    /// Remove content above `freq`.
+    LowPass,
+}
+
+fn default_q() -> f32 {
+    std::f32::consts::FRAC_1_SQRT_2
+}
+
+/// One band of a parametric EQ preset, e.g. `{"type": "low_shelf", "freq": 200, "gain_db": -3}`.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+pub struct EqBand {
+    #[serde(rename = "type")]
+    pub kind: FilterKind,
+    /// Center or corner frequency in Hz.
+    pub freq: f32,
+    /// Ignored by the pass filters.
+    #[serde(default)]
+    pub gain_db: f32,
+    #[serde(default = "default_q")]
+    pub q: f32,
+}
+
+/// A second-order IIR section in transposed direct form II.
+#[derive(Clone, Debug)]
+struct Biquad {
+    b: [f32; 3],
+    a: [f32; 2],
+    state: [f32; 2],
+}
+
+impl Biquad {
+    fn new(band: &EqBand, sample_rate: u32) -> Result<Self, String> {
+        let nyquist = sample_rate as f32 / 2.0;
+        if !(band.freq > 0.0 && band.freq < nyquist) {
+            return Err(format!("EQ frequency must be between 0 and {} Hz", nyquist));
+        }
+        if !(band.q > 0.0 && band.gain_db.is_finite()) {
+            return Err("EQ q must be positive and gain_db finite".to_string());
+        }
+
+        let gain = 10f64.powf(f64::from(band.gain_db) / 40.0);
+        let w0 = 2.0 * std::f64::consts::PI * f64::from(band.freq) / f64::from(sample_rate);
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * f64::from(band.q));
+        let shelf = 2.0 * gain.sqrt() * alpha;
+        let (b, a) = match band.kind {
+            FilterKind::Peaking => (
+                [1.0 + alpha * gain, -2.0 * cos, 1.0 - alpha * gain],
+                [1.0 + alpha / gain, -2.0 * cos, 1.0 - alpha / gain],
+            ),
+            FilterKind::LowShelf => (
+                [
+                    gain * ((gain + 1.0) - (gain - 1.0) * cos + shelf),
+                    2.0 * gain * ((gain - 1.0) - (gain + 1.0) * cos),
+                    gain * ((gain + 1.0) - (gain - 1.0) * cos - shelf),
+                ],
+                [
+                    (gain + 1.0) + (gain - 1.0) * cos + shelf,
+                    -2.0 * ((gain - 1.0) + (gain + 1.0) * cos),
+                    (gain + 1.0) + (gain - 1.0) * cos - shelf,
+                ],
+            ),
+            FilterKind::HighShelf => (
+                [
+                    gain * ((gain + 1.0) + (gain - 1.0) * cos + shelf),
+                    -2.0 * gain * ((gain - 1.0) + (gain + 1.0) * cos),
+                    gain * ((gain + 1.0) + (gain - 1.0) * cos - shelf),
+                ],
+                [
+                    (gain + 1.0) - (gain - 1.0) * cos + shelf,
+                    2.0 * ((gain - 1.0) - (gain + 1.0) * cos),
+                    (gain + 1.0) - (gain - 1.0) * cos - shelf,
+                ],
+            ),
+            FilterKind::HighPass => (
+                [(1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0],
+                [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+            ),
+            FilterKind::LowPass => (
+                [(1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0],
+                [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+            ),
+        };
+        // Normalize so that a0 is 1.
+        Ok(Biquad {
+            b: b.map(|coef| (coef / a[0]) as f32),
+            a: [(a[1] / a[0]) as f32, (a[2] / a[0]) as f32],
+            state: [0.0; 2],
+        })
+    }
+
+    fn process(&mut self, sample: f32) -> f32 {
+        let out = self.b[0] * sample + self.state[0];
+        self.state[0] = self.b[1] * sample - self.a[0] * out + self.state[1];
+        self.state[1] = self.b[2] * sample - self.a[1] * out;
+        out
+    }
+}
+
+/// A chain of EQ bands applied in order. Like [`DcBlocker`], it keeps its state
+/// between calls so that a stream can be processed chunk by chunk.
+#[derive(Clone, Debug)]
+pub struct Equalizer {
+    sections: Vec<Biquad>,
+}
+
+impl Equalizer {
+    pub fn new(bands: &[EqBand], sample_rate: u32) -> Result<Self, String> {
+        let sections = bands
+            .iter()
+            .map(|band| Biquad::new(band, sample_rate))
+            .collect::<Result<_, _>>()?;
+        Ok(Equalizer { sections })
+    }
+
+    pub fn process(&mut self, samples: &mut [f32]) {
+        for sample in samples.iter_mut() {
+            *sample = self
+                .sections
+                .iter_mut()
+                .fold(*sample, |value, section| section.process(value));
+        }
+    }
+}
+
+/// Peak level of the dither floor: one 16-bit LSB.
+const DITHER_LSB: f32 = 1.0 / 32768.0;
+
+/// What inserted silence is made of.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SilenceFill {
+    /// Digital zeros.
+    #[default]
+    Zero,
+    /// Triangular dither at the 16-bit noise floor, so that pauses do not sound
+    /// like holes next to speech, especially after lossy encoding.
+    Dither,
+}
+
+/// Produces the silence inserted into one request's audio. The dither noise
+/// continues from one pause to the next instead of restarting, so consecutive
+/// pauses differ, while the fixed seed keeps output reproducible across runs.
+pub struct SilenceSource {
+    fill: SilenceFill,
+    state: u32,
+}
+
+impl SilenceSource {
+    pub fn new(fill: SilenceFill) -> Self {
+        SilenceSource { fill, state: 0x9E37_79B9 }
+    }
+
+    /// Silence of the given duration.
+    pub fn silence(&mut self, duration_ms: u32, sample_rate: u32) -> Vec<f32> {
+        let len = (u64::from(duration_ms) * u64::from(sample_rate) / 1000) as usize;
+        match self.fill {
+            SilenceFill::Zero => vec![0.0; len],
+            SilenceFill::Dither => (0..len)
+                .map(|_| (self.uniform() + self.uniform()) * DITHER_LSB)
+                .collect(),
+        }
+    }
+
+    /// The next xorshift value, uniform in [-0.5, 0.5].
+    fn uniform(&mut self) -> f32 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        self.state as f32 / u32::MAX as f32 - 0.5
+    }
+}
+
+/// A sine wave whose peak is `level_dbfs` relative to full scale, e.g. the 1 kHz
+/// reference tone at -18 dBFS that level meters are aligned to. It starts at zero
+/// phase, so it begins without a click.
+pub fn sine_tone(frequency: f32, level_dbfs: f32, duration_ms: u32, sample_rate: u32) -> Vec<f32> {
+    let len = (u64::from(duration_ms) * u64::from(sample_rate) / 1000) as usize;
+    let amplitude = 10f64.powf(f64::from(level_dbfs) / 20.0);
+    let step = std::f64::consts::TAU * f64::from(frequency) / f64::from(sample_rate);
+    (0..len)
+        .map(|i| (amplitude * (step * i as f64).sin()) as f32)
+        .collect()
+}
+
+/// Start of the last stretch of sound that follows at least `min_gap_ms` of silence,
+/// e.g. the last word after a sentence break. Levels are measured over 10 ms frames,
+/// and frames with an RMS below `threshold` count as silent. Leading silence is not a
+/// gap, so `None` is returned unless sound precedes the gap.
+pub fn last_onset_after_gap(samples: &[f32], sample_rate: u32, min_gap_ms: u32, threshold: f32) -> Option<usize> {
+    let frame_len = (sample_rate / 100).max(1) as usize;
+    let min_gap_frames = (min_gap_ms / 10).max(1) as usize;
+    let mut heard_sound = false;
+    let mut silent_frames = 0;
+    let mut onset = None;
+    for (i, frame) in samples.chunks(frame_len).enumerate() {
+        if rms(frame) < threshold {
+            silent_frames += 1;
+            continue;
+        }
+        if heard_sound && silent_frames >= min_gap_frames {
+            onset = Some(i * frame_len);
+        }
+        heard_sound = true;
+        silent_frames = 0;
+    }
+    onset
+}
+
+/// Shortens trailing silence to at most `keep_ms`. Levels are measured over 10 ms
+/// frames, and frames with an RMS below `threshold` count as silent.
+pub fn trim_trailing_silence(samples: &mut Vec<f32>, sample_rate: u32, keep_ms: u32, threshold: f32) {
+    let frame_len = (sample_rate / 100).max(1) as usize;
+    let sound_end = samples
+        .chunks(frame_len)
+        .rposition(|frame| rms(frame) >= threshold)
+        .map_or(0, |i| ((i + 1) * frame_len).min(samples.len()));
+    let keep = (u64::from(keep_ms) * u64::from(sample_rate) / 1000) as usize;
+    samples.truncate(sound_end + keep);
+}
+
+/// Zero crossings of the windowed-sinc kernel on each side of a resampled point.
+const RESAMPLE_HALF_TAPS: f64 = 16.0;
+
+/// Converts audio between sample rates with a Hann-windowed sinc kernel, low-pass
+/// filtered below the lower of the two Nyquist frequencies so that downsampling does
+/// not alias.
+pub fn resample(samples: &[f32], from_rate: u32, to_rate: u32) -> Vec<f32> {
+    if from_rate == to_rate || samples.is_empty() {
+        return samples.to_vec();
+    }
+    let ratio = f64::from(to_rate) / f64::from(from_rate);
+    // Kernel width in input samples, widened when downsampling to lower the cutoff.
+    let scale = ratio.min(1.0);
+    let half_width = RESAMPLE_HALF_TAPS / scale;
+    let len = (samples.len() as f64 * ratio).round() as usize;
+    (0..len)
+        .map(|i| {
+            let center = i as f64 / ratio;
+            let first = (center - half_width).ceil().max(0.0) as usize;
+            let last = ((center + half_width).floor() as usize).min(samples.len() - 1);
+            let mut sum = 0.0;
+            let mut weights = 0.0;
+            for (j, &sample) in samples.iter().enumerate().take(last + 1).skip(first) {
+                let x = (j as f64 - center) * scale;
+                let sinc = if x == 0.0 {
+                    1.0
+                } else {
+                    (std::f64::consts::PI * x).sin() / (std::f64::consts::PI * x)
+                };
+                let window = 0.5 + 0.5 * (std::f64::consts::PI * x / RESAMPLE_HALF_TAPS).cos();
+                let weight = sinc * window;
+                sum += f64::from(sample) * weight;
+                weights += weight;
+            }
+            // Normalizing by the weights keeps the gain at 1, also near the edges.
+            if weights.abs() > f64::EPSILON { (sum / weights) as f32 } else { 0.0 }
+        })
+        .collect()
+}
+
+/// Converts an f32 sample to 16-bit PCM, saturating at full scale.
+pub fn to_i16(sample: f32) -> i16 {
+    (sample.clamp(-1.0, 1.0) * 32767.0) as i16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clip_policies() {
+        let input = [0.5f32, 2.0, -1.5];
+
+        let mut clamped = input;
+        let stats = ClipPolicy::Clamp.apply(&mut clamped, None);
+        assert_eq!(clamped, [0.5, 1.0, -1.0]);
+        assert_eq!(stats, ClipStats { clipped_samples: 2, peak: 2.0 });
+        assert!(!ClipPolicy::Clamp.apply(&mut clamped, None).clipped());
+
+        let mut limited = input;
+        ClipPolicy::SoftLimit.apply(&mut limited, Some(0.0));
+        assert_eq!(limited[0], 0.5);
+        assert!(limited[1] > SOFT_LIMIT_THRESHOLD && limited[1] <= 1.0);
+        assert!(limited[2] < -SOFT_LIMIT_THRESHOLD && limited[2] >= -1.0);
+
+        // Limiting is reported even when the input stays within full scale.
+        let mut limited = [0.5f32, 0.85, -0.2];
+        let stats = ClipPolicy::SoftLimit.apply(&mut limited, None);
+        assert_eq!(stats, ClipStats { clipped_samples: 1, peak: 0.85 });
+        assert!(limited[1] < 0.85);
+        assert!(!ClipPolicy::Clamp.apply(&mut [0.5f32, 0.85, -0.2], None).clipped());
+
+        // Without a ceiling, peaks are kept under -1 dBFS.
+        let ceiling = dbfs_to_gain(DEFAULT_LIMITER_CEILING_DBFS);
+        assert!((ceiling - 0.891).abs() < 0.001);
+        let mut limited = input;
+        ClipPolicy::SoftLimit.apply(&mut limited, None);
+        assert_eq!(limited[0], 0.5);
+        assert!(peak(&limited) <= ceiling && peak(&limited) > SOFT_LIMIT_THRESHOLD * ceiling);
+
+        let mut limited: Vec<f32> = (0..=300).map(|i| i as f32 / 100.0 - 1.5).collect();
+        ClipPolicy::SoftLimit.apply(&mut limited, Some(-3.0));
+        assert!(peak(&limited) <= dbfs_to_gain(-3.0));
+        // The curve is monotonic, so the waveform's shape is kept.
+        assert!(limited.windows(2).all(|w| w[0] <= w[1]));
+
+        let mut normalized = input;
+        ClipPolicy::Normalize.apply(&mut normalized, None);
+        assert_eq!(normalized, [0.25, 1.0, -0.75]);
+    }
+
+    #[test]
+    fn test_dc_blocker() {
+        let mut samples: Vec<f32> = (0..4800).map(|i| 0.2 + 0.5 * (i as f32 * 0.3).sin()).collect();
+        let mut blocker = DcBlocker::default();
+        let (head, tail) = samples.split_at_mut(2400);
+        blocker.process(head);
+        blocker.process(tail);
+
+        let settled = &samples[2400..];
+        let mean = settled.iter().sum::<f32>() / settled.len() as f32;
+        assert!(mean.abs() < 0.01, "{}", mean);
+        assert!(peak(settled) > 0.45);
+    }
+
+    #[test]
+    fn test_equalizer() {
+        let sine = |freq: f32| -> Vec<f32> {
+            (0..24000)
+                .map(|i| 0.25 * (2.0 * std::f32::consts::PI * freq * i as f32 / 24000.0).sin())
+                .collect()
+        };
+        let band = |kind, freq, gain_db| EqBand { kind, freq, gain_db, q: default_q() };
+
+        // A +6 dB peak doubles the level at its center and leaves distant content alone.
+        let mut eq = Equalizer::new(&[band(FilterKind::Peaking, 1000.0, 6.02)], 24000).unwrap();
+        let mut center = sine(1000.0);
+        eq.process(&mut center);
+        assert!((rms(&center[2400..]) / rms(&sine(1000.0)[2400..]) - 2.0).abs() < 0.05);
+
+        let mut eq = Equalizer::new(&[band(FilterKind::Peaking, 1000.0, 6.02)], 24000).unwrap();
+        let mut distant = sine(8000.0);
+        eq.process(&mut distant);
+        assert!((rms(&distant[2400..]) / rms(&sine(8000.0)[2400..]) - 1.0).abs() < 0.05);
+
+        // A high-pass removes rumble far below its corner.
+        let mut eq = Equalizer::new(&[band(FilterKind::HighPass, 1000.0, 0.0)], 24000).unwrap();
+        let mut rumble = sine(50.0);
+        eq.process(&mut rumble);
+        assert!(rms(&rumble[2400..]) < 0.01);
+
+        assert!(Equalizer::new(&[band(FilterKind::LowShelf, 20000.0, 3.0)], 24000).is_err());
+        assert!(Equalizer::new(&[band(FilterKind::LowShelf, 0.0, 3.0)], 24000).is_err());
+    }
+
+    #[test]
+    fn test_last_onset_after_gap() {
+        let tone = |ms: usize| (0..ms * 24).map(|i| 0.5 * (i as f32 * 0.3).sin()).collect::<Vec<f32>>();
+        let quiet = |ms: usize| vec![0.0f32; ms * 24];
+        let audio = [quiet(50), tone(300), quiet(200), tone(100), quiet(30), tone(100), quiet(50)].concat();
+        assert_eq!(last_onset_after_gap(&audio, 24000, 100, 0.01), Some(550 * 24));
+        assert_eq!(last_onset_after_gap(&audio, 24000, 20, 0.01), Some(680 * 24));
+        assert_eq!(last_onset_after_gap(&[quiet(200), tone(100)].concat(), 24000, 100, 0.01), None);
+    }
+
+    #[test]
+    fn test_trim_trailing_silence() {
+        let tone = |ms: usize| (0..ms * 24).map(|i| 0.5 * (i as f32 * 0.3).sin()).collect::<Vec<f32>>();
+        let quiet = |ms: usize| vec![0.0f32; ms * 24];
+        let mut audio = [quiet(50), tone(100), quiet(400)].concat();
+        trim_trailing_silence(&mut audio, 24000, 100, 0.01);
+        assert_eq!(audio.len(), 250 * 24);
+
+        // Shorter tails are kept as they are.
+        let mut audio = [tone(100), quiet(30)].concat();
+        trim_trailing_silence(&mut audio, 24000, 100, 0.01);
+        assert_eq!(audio.len(), 130 * 24);
+
+        let mut audio = quiet(300);
+        trim_trailing_silence(&mut audio, 24000, 100, 0.01);
+        assert_eq!(audio.len(), 100 * 24);
+    }
+
+    #[test]
+    fn test_resample() {
+        // A 1 kHz tone keeps its level and frequency when converted from 48 kHz.
+        let tone = sine_tone(1000.0, -6.0, 100, 48000);
+        let resampled = resample(&tone, 48000, 24000);
+        assert_eq!(resampled.len(), 2400);
+        let steady = &resampled[100..2300];
+        assert!((rms(steady) - rms(&sine_tone(1000.0, -6.0, 100, 24000))).abs() < 0.005);
+        let crossings = steady.windows(2).filter(|w| w[0] < 0.0 && w[1] >= 0.0).count();
+        assert!((90..=92).contains(&crossings), "{}", crossings);
+
+        // Content above the new Nyquist frequency is filtered out instead of aliasing.
+        let high = sine_tone(18000.0, -6.0, 100, 48000);
+        assert!(rms(&resample(&high, 48000, 24000)[100..2300]) < 0.01);
+
+        assert_eq!(resample(&sine_tone(1000.0, -6.0, 100, 16000), 16000, 24000).len(), 2400);
+        assert_eq!(resample(&[0.5; 10], 24000, 24000), vec![0.5; 10]);
+    }
+
+    #[test]
+    fn test_sine_tone() {
+        let tone = sine_tone(1000.0, -18.0, 1000, 24000);
+        assert_eq!(tone.len(), 24000);
+        assert_eq!(tone[0], 0.0);
+        // -18 dBFS is a peak of 0.1259, and a sine's RMS is its peak over the square root of 2.
+        assert!((peak(&tone) - 0.125_893).abs() < 1e-5);
+        assert!((rms(&tone) - 0.125_893 / std::f32::consts::SQRT_2).abs() < 1e-5);
+        assert!((peak(&sine_tone(1000.0, 0.0, 10, 24000)) - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_silence_fill() {
+        let mut zero = SilenceSource::new(SilenceFill::Zero);
+        assert_eq!(zero.silence(10, 24000), vec![0.0; 240]);
+
+        let mut source = SilenceSource::new(SilenceFill::Dither);
+        let dither = source.silence(10, 24000);
+        assert_eq!(dither.len(), 240);
+        assert!(peak(&dither) > 0.0 && peak(&dither) <= DITHER_LSB);
+        // Consecutive pauses get different noise, but a new request repeats it.
+        assert_ne!(dither, source.silence(10, 24000));
+        assert_eq!(dither, SilenceSource::new(SilenceFill::Dither).silence(10, 24000));
+    }
+}
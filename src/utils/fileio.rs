@@ -1,5 +1,6 @@
 use indicatif::{ProgressBar, ProgressStyle};
 use serde_json::Value;
+use sha2::{Digest, Sha256};
 use std::{io::Read, path::Path};
 use tokio::{fs::File, io::AsyncWriteExt};
 
@@ -54,3 +55,23 @@ pub fn load_json_file(path: &str) -> Result<Value, String> {
 
     Ok(json_value)
 }
+
+/// Hex-encoded SHA-256 of a file's contents, read in blocks so large model files
+/// are not held in memory.
+pub fn file_sha256(path: &str) -> std::io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 1 << 20];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
@@ -1,2 +1,4 @@
+pub mod caf;
+pub mod dsp;
 pub mod fileio;
 pub mod wav;
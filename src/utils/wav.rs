@@ -1,9 +1,105 @@
 use std::io::{self, Write};
 
+use serde::Deserialize;
+
+use crate::utils::dsp::{peak_position, to_i16};
+
+// WAVE format tags.
+const FORMAT_PCM: u16 = 1;
+const FORMAT_IEEE_FLOAT: u16 = 3;
+
+// Field sizes of a version 1 `bext` chunk, from EBU Tech 3285.
+const BEXT_DESCRIPTION_LEN: usize = 256;
+const BEXT_ORIGINATOR_LEN: usize = 32;
+const BEXT_ORIGINATOR_REFERENCE_LEN: usize = 32;
+const BEXT_UMID_LEN: usize = 64;
+const BEXT_RESERVED_LEN: usize = 190;
+const BEXT_CHUNK_LEN: u32 = 602;
+
+/// Origination metadata of a Broadcast Wave Format file, written as a `bext` chunk.
+/// The time reference is always 0, i.e. the audio starts at midnight, and the
+/// origination date and time are when the file is written, in UTC.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Bext {
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub originator: String,
+    #[serde(default)]
+    pub originator_reference: String,
+}
+
+impl Bext {
+    /// Checks that the fields are ASCII and fit their fixed sizes in the chunk.
+    pub fn validate(&self) -> Result<(), String> {
+        let fields = [
+            ("description", &self.description, BEXT_DESCRIPTION_LEN),
+            ("originator", &self.originator, BEXT_ORIGINATOR_LEN),
+            ("originator_reference", &self.originator_reference, BEXT_ORIGINATOR_REFERENCE_LEN),
+        ];
+        for (name, value, len) in fields {
+            if !value.is_ascii() {
+                return Err(format!("bext {} must be ASCII", name));
+            }
+            if value.len() > len {
+                return Err(format!("bext {} must be at most {} characters", name, len));
+            }
+        }
+        Ok(())
+    }
+
+    fn write<W: Write>(&self, writer: &mut W, unix_secs: u64) -> io::Result<()> {
+        let mut write_padded = |value: &str, len: usize| {
+            let mut field = vec![0u8; len];
+            field[..value.len()].copy_from_slice(value.as_bytes());
+            writer.write_all(&field)
+        };
+        write_padded(&self.description, BEXT_DESCRIPTION_LEN)?;
+        write_padded(&self.originator, BEXT_ORIGINATOR_LEN)?;
+        write_padded(&self.originator_reference, BEXT_ORIGINATOR_REFERENCE_LEN)?;
+
+        let (year, month, day) = civil_date((unix_secs / 86400) as i64);
+        let seconds = unix_secs % 86400;
+        write_padded(&format!("{:04}-{:02}-{:02}", year, month, day), 10)?;
+        write_padded(
+            &format!("{:02}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60),
+            8,
+        )?;
+
+        writer.write_all(&(0u64).to_le_bytes())?; // Time reference, low and high words
+        writer.write_all(&(1u16).to_le_bytes())?; // Version
+        writer.write_all(&[0; BEXT_UMID_LEN])?;
+        writer.write_all(&[0; BEXT_RESERVED_LEN])?;
+        Ok(())
+    }
+}
+
+/// Year, month and day of a day count since 1970-01-01, in the proleptic Gregorian calendar.
+fn civil_date(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_from_march + 2) / 5 + 1) as u32;
+    let month = if month_from_march < 10 { month_from_march + 3 } else { month_from_march - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Header of a WAV file. Files of unknown length, which are streamed, get placeholder
+/// RIFF and data sizes that players read as "until the end".
+///
+/// 32-bit samples are written as IEEE float, 16-bit samples as integer PCM.
 pub struct WavHeader {
     pub channels: u16,
     pub sample_rate: u32,
     pub bits_per_sample: u16,
+    /// Peak level and frame position, written as a `PEAK` chunk for float files.
+    pub peak: Option<(f32, u32)>,
+    /// Broadcast metadata, written as a `bext` chunk.
+    pub bext: Option<Bext>,
 }
 
 impl WavHeader {
@@ -12,19 +108,73 @@ impl WavHeader {
             channels,
             sample_rate,
             bits_per_sample,
+            peak: None,
+            bext: None,
         }
     }
 
-    pub fn write_header<W: Write>(&self, writer: &mut W) -> io::Result<()> {
-        // RIFF header
+    /// Records the peak of mono `samples`, so that DAWs can show levels without scanning.
+    pub fn with_peak(mut self, samples: &[f32]) -> Self {
+        let (value, position) = peak_position(samples);
+        self.peak = Some((value, position as u32));
+        self
+    }
+
+    /// Makes the file a Broadcast Wave Format file with the given metadata.
+    pub fn with_bext(mut self, bext: Bext) -> Self {
+        self.bext = Some(bext);
+        self
+    }
+
+    /// Writes the header up to the data chunk's samples. With `num_frames`, the RIFF and
+    /// data sizes are exact; without it (streaming), or if they do not fit in 32 bits,
+    /// they are placeholders.
+    pub fn write_header<W: Write>(&self, writer: &mut W, num_frames: Option<u64>) -> io::Result<()> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        self.write_header_at(writer, num_frames, now)
+    }
+
+    fn write_header_at<W: Write>(&self, writer: &mut W, num_frames: Option<u64>, unix_secs: u64) -> io::Result<()> {
+        // Everything between the RIFF size and the data size, to size the file
+        let mut chunks = Vec::new();
+        self.write_chunks(&mut chunks, unix_secs)?;
+        let block_align = u64::from(self.channels * self.bits_per_sample / 8);
+        let sizes = num_frames.and_then(|frames| {
+            let data_size = u32::try_from(frames * block_align).ok()?;
+            let riff_size = u32::try_from(chunks.len() as u64 + 4 + u64::from(data_size)).ok()?;
+            Some((riff_size, data_size))
+        });
+        let (riff_size, data_size) = sizes.unwrap_or((u32::MAX, u32::MAX));
+
         writer.write_all(b"RIFF")?;
-        writer.write_all(&[0xFF, 0xFF, 0xFF, 0xFF])?; // File size - 8 (placeholder)
+        writer.write_all(&riff_size.to_le_bytes())?; // File size - 8
+        writer.write_all(&chunks)?;
+        writer.write_all(&data_size.to_le_bytes())?;
+        Ok(())
+    }
+
+    /// Writes the `WAVE` form type, the chunks before the data and the data chunk's ID.
+    fn write_chunks<W: Write>(&self, writer: &mut W, unix_secs: u64) -> io::Result<()> {
         writer.write_all(b"WAVE")?;
 
+        // Broadcast extension chunk, which EBU Tech 3285 places before the format chunk
+        if let Some(bext) = &self.bext {
+            writer.write_all(b"bext")?;
+            writer.write_all(&BEXT_CHUNK_LEN.to_le_bytes())?;
+            bext.write(writer, unix_secs)?;
+        }
+
         // Format chunk
         writer.write_all(b"fmt ")?;
         writer.write_all(&(16u32).to_le_bytes())?; // Format chunk size
-        writer.write_all(&(3u16).to_le_bytes())?; // Format = 3 (IEEE float)
+        let format = if self.bits_per_sample == 16 {
+            FORMAT_PCM
+        } else {
+            FORMAT_IEEE_FLOAT
+        };
+        writer.write_all(&format.to_le_bytes())?;
         writer.write_all(&self.channels.to_le_bytes())?;
         writer.write_all(&self.sample_rate.to_le_bytes())?;
         let byte_rate =
@@ -34,12 +184,34 @@ impl WavHeader {
         writer.write_all(&block_align.to_le_bytes())?;
         writer.write_all(&self.bits_per_sample.to_le_bytes())?;
 
-        // Data chunk header
+        // Peak chunk, only defined for float data
+        if let (Some((value, position)), FORMAT_IEEE_FLOAT) = (self.peak, format) {
+            let timestamp = unix_secs as u32;
+            writer.write_all(b"PEAK")?;
+            writer.write_all(&(16u32).to_le_bytes())?; // Chunk size for one channel
+            writer.write_all(&(1u32).to_le_bytes())?; // Version
+            writer.write_all(&timestamp.to_le_bytes())?;
+            writer.write_all(&value.to_le_bytes())?;
+            writer.write_all(&position.to_le_bytes())?;
+        }
+
+        // Data chunk ID, followed by its size
         writer.write_all(b"data")?;
-        writer.write_all(&[0xFF, 0xFF, 0xFF, 0xFF])?; // Data size (placeholder)
 
         Ok(())
     }
+
+    /// Writes samples in the format described by this header.
+    pub fn write_samples<W: Write>(&self, writer: &mut W, samples: &[f32]) -> io::Result<()> {
+        match self.bits_per_sample {
+            32 => write_audio_chunk(writer, samples),
+            16 => write_audio_chunk_i16(writer, samples),
+            bits => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unsupported WAV bit depth: {}", bits),
+            )),
+        }
+    }
 }
 
 pub fn write_audio_chunk<W: Write>(writer: &mut W, samples: &[f32]) -> io::Result<()> {
@@ -48,3 +220,121 @@ pub fn write_audio_chunk<W: Write>(writer: &mut W, samples: &[f32]) -> io::Resul
     }
     Ok(())
 }
+
+/// Writes samples as 16-bit integer PCM, saturating at full scale.
+pub fn write_audio_chunk_i16<W: Write>(writer: &mut W, samples: &[f32]) -> io::Result<()> {
+    for &sample in samples {
+        writer.write_all(&to_i16(sample).to_le_bytes())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wav_int16() {
+        let header = WavHeader::new(1, 24000, 16);
+        let mut out = Vec::new();
+        header.write_header(&mut out, None).unwrap();
+        header.write_samples(&mut out, &[1.0, -2.0]).unwrap();
+
+        assert_eq!(u16::from_le_bytes([out[20], out[21]]), FORMAT_PCM);
+        assert_eq!(u32::from_le_bytes(out[28..32].try_into().unwrap()), 24000 * 2);
+        assert_eq!(&out[44..], &[0xFF, 0x7F, 0x01, 0x80]);
+
+        let mut float = Vec::new();
+        WavHeader::new(1, 24000, 32).write_header(&mut float, None).unwrap();
+        assert_eq!(u16::from_le_bytes([float[20], float[21]]), FORMAT_IEEE_FLOAT);
+    }
+
+    #[test]
+    fn test_wav_sizes() {
+        let size = |out: &[u8], at: usize| u32::from_le_bytes(out[at..at + 4].try_into().unwrap());
+        let samples = [0.25f32, -0.5, 0.75];
+        let bext = Bext {
+            description: "Evening news".to_string(),
+            ..Bext::default()
+        };
+        for header in [
+            WavHeader::new(1, 24000, 16),
+            WavHeader::new(1, 24000, 32).with_peak(&samples),
+            WavHeader::new(1, 24000, 32).with_peak(&samples).with_bext(bext),
+        ] {
+            let mut out = Vec::new();
+            header.write_header(&mut out, Some(samples.len() as u64)).unwrap();
+            let data_at = out.len() - 8;
+            header.write_samples(&mut out, &samples).unwrap();
+
+            assert_eq!(size(&out, 4) as usize, out.len() - 8);
+            assert_eq!(&out[data_at..data_at + 4], b"data");
+            assert_eq!(size(&out, data_at + 4) as usize, out.len() - data_at - 8);
+            let bytes_per_sample = usize::from(header.bits_per_sample / 8);
+            assert_eq!(size(&out, data_at + 4) as usize, samples.len() * bytes_per_sample);
+        }
+
+        // Streamed files have placeholder sizes.
+        let mut out = Vec::new();
+        WavHeader::new(1, 24000, 32).write_header(&mut out, None).unwrap();
+        assert_eq!(size(&out, 4), u32::MAX);
+        assert_eq!(size(&out, 40), u32::MAX);
+    }
+
+    #[test]
+    fn test_wav_peak_chunk() {
+        let samples = [0.1f32, -0.8, 0.5];
+        let mut out = Vec::new();
+        WavHeader::new(1, 24000, 32)
+            .with_peak(&samples)
+            .write_header(&mut out, None)
+            .unwrap();
+
+        assert_eq!(&out[36..40], b"PEAK");
+        assert_eq!(u32::from_le_bytes(out[40..44].try_into().unwrap()), 16);
+        assert_eq!(f32::from_le_bytes(out[52..56].try_into().unwrap()), 0.8);
+        assert_eq!(u32::from_le_bytes(out[56..60].try_into().unwrap()), 1);
+        assert_eq!(&out[60..64], b"data");
+
+        // Integer files have no PEAK chunk.
+        let mut int = Vec::new();
+        WavHeader::new(1, 24000, 16).with_peak(&samples).write_header(&mut int, None).unwrap();
+        assert_eq!(&int[36..40], b"data");
+    }
+
+    #[test]
+    fn test_wav_bext_chunk() {
+        let bext = Bext {
+            description: "Evening news".to_string(),
+            originator: "Kokoros".to_string(),
+            originator_reference: "ref-1".to_string(),
+        };
+        let mut out = Vec::new();
+        // 2024-02-29 13:45:30 UTC
+        WavHeader::new(1, 24000, 16)
+            .with_bext(bext)
+            .write_header_at(&mut out, None, 1_709_214_330)
+            .unwrap();
+
+        assert_eq!(&out[12..16], b"bext");
+        assert_eq!(u32::from_le_bytes(out[16..20].try_into().unwrap()), 602);
+        let chunk = &out[20..20 + 602];
+        assert_eq!(&chunk[..12], b"Evening news");
+        assert!(chunk[12..256].iter().all(|&b| b == 0));
+        assert_eq!(&chunk[256..263], b"Kokoros");
+        assert_eq!(&chunk[288..293], b"ref-1");
+        assert_eq!(&chunk[320..330], b"2024-02-29");
+        assert_eq!(&chunk[330..338], b"13:45:30");
+        assert_eq!(u64::from_le_bytes(chunk[338..346].try_into().unwrap()), 0);
+        assert_eq!(u16::from_le_bytes([chunk[346], chunk[347]]), 1);
+        // The format chunk follows.
+        assert_eq!(&out[622..626], b"fmt ");
+
+        assert_eq!(civil_date(0), (1970, 1, 1));
+        assert_eq!(civil_date(10_957), (2000, 1, 1));
+
+        assert!(Bext { originator: "x".repeat(33), ..Bext::default() }.validate().is_err());
+        assert!(Bext { description: "Café".to_string(), ..Bext::default() }.validate().is_err());
+        assert!(Bext { description: "d".repeat(256), ..Bext::default() }.validate().is_ok());
+    }
+}